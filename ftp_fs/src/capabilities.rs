@@ -0,0 +1,74 @@
+//! Что умеет FTP-сервер: ответы на FEAT и SYST после входа.
+
+use serde::Serialize;
+
+/// Возможности сервера, выясненные сразу после входа.
+///
+/// По ним [`crate::FtpFs`] выбирает команды: то, чего сервер не объявил,
+/// не пробуется. Если сервер не ответил на FEAT (`feat == false`), флаги
+/// ничего не значат и команды пробуются наудачу.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ServerCapabilities {
+    /// Приветствие сервера без кодов ответа.
+    pub welcome: Option<String>,
+    /// Тип системы из ответа на SYST, например `UNIX Type: L8`.
+    pub system: Option<String>,
+    /// Сервер ответил на FEAT.
+    pub feat: bool,
+    /// Машиночитаемые листинги MLSD и MLST (объявляются строкой `MLST`).
+    pub mlsd: bool,
+    /// Размер файла командой SIZE.
+    pub size: bool,
+    /// Время изменения файла командой MDTM.
+    pub mdtm: bool,
+    /// Установка времени изменения командой MFMT.
+    pub mfmt: bool,
+    /// Докачка командой REST в потоковом режиме.
+    pub rest_stream: bool,
+    /// Имена файлов в UTF-8.
+    pub utf8: bool,
+    /// Алгоритмы контрольных сумм команды HASH; пусто — HASH нет.
+    pub hash: Vec<String>,
+    /// Расширенный пассивный режим EPSV.
+    pub epsv: bool,
+    /// Прочие объявленные возможности, как их прислал сервер.
+    pub other: Vec<String>,
+}
+
+impl ServerCapabilities {
+    /// Разобрать ответ на FEAT: пары «имя возможности — параметры».
+    ///
+    /// Имена сравниваются без учёта регистра; звёздочка у алгоритма HASH
+    /// (выбранный сейчас) отбрасывается.
+    pub fn from_features(features: impl IntoIterator<Item = (String, Option<String>)>) -> Self {
+        let mut capabilities = Self {
+            feat: true,
+            ..Self::default()
+        };
+        for (name, params) in features {
+            let params = params.unwrap_or_default();
+            match name.to_ascii_uppercase().as_str() {
+                "MLST" | "MLSD" => capabilities.mlsd = true,
+                "SIZE" => capabilities.size = true,
+                "MDTM" => capabilities.mdtm = true,
+                "MFMT" => capabilities.mfmt = true,
+                "REST" if params.eq_ignore_ascii_case("STREAM") => capabilities.rest_stream = true,
+                "UTF8" => capabilities.utf8 = true,
+                "HASH" => {
+                    capabilities.hash = params
+                        .split(';')
+                        .map(|algorithm| algorithm.trim().trim_end_matches('*'))
+                        .filter(|algorithm| !algorithm.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                "EPSV" => capabilities.epsv = true,
+                _ if params.is_empty() => capabilities.other.push(name),
+                _ => capabilities.other.push(format!("{} {}", name, params)),
+            }
+        }
+        capabilities.other.sort();
+        capabilities
+    }
+}
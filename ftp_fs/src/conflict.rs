@@ -0,0 +1,116 @@
+//! Что делать с файлом, который уже есть в месте назначения.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::SkipReason;
+
+/// Политика для файлов, которые уже есть в месте назначения.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Перезаписать.
+    #[default]
+    Overwrite,
+    /// Не передавать.
+    Skip,
+    /// Сохранить под свободным именем: `report (1).pdf`.
+    Rename,
+    /// Перезаписать, если источник новее. Если время изменения одной из
+    /// сторон неизвестно (сервер не поддерживает ни MDTM, ни MLST) — перезаписать.
+    OverwriteIfNewer,
+    /// Перезаписать, если размеры различаются.
+    OverwriteIfSizeDiffers,
+}
+
+/// Как поступить с конкретным файлом по [`ConflictPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Transfer,
+    Skip(SkipReason),
+    Rename,
+}
+
+impl ConflictPolicy {
+    /// Решение для файла `source`, на месте которого уже лежит `target`.
+    ///
+    /// Время изменения сравнивается с точностью до секунды — точнее MDTM
+    /// его не сообщает.
+    pub fn resolve(self, source: &FileStat, target: &FileStat) -> Resolution {
+        match self {
+            Self::Overwrite => Resolution::Transfer,
+            Self::Skip => Resolution::Skip(SkipReason::Exists),
+            Self::Rename => Resolution::Rename,
+            Self::OverwriteIfNewer => match (source.modified_secs(), target.modified_secs()) {
+                (Some(source), Some(target)) if source <= target => {
+                    Resolution::Skip(SkipReason::UpToDate)
+                }
+                _ => Resolution::Transfer,
+            },
+            Self::OverwriteIfSizeDiffers => match (source.size, target.size) {
+                (Some(source), Some(target)) if source == target => {
+                    Resolution::Skip(SkipReason::UpToDate)
+                }
+                _ => Resolution::Transfer,
+            },
+        }
+    }
+
+    /// Нужно ли для решения время изменения файлов (команда MDTM).
+    pub(crate) fn needs_modified(self) -> bool {
+        self == Self::OverwriteIfNewer
+    }
+}
+
+/// Размер и время изменения файла; `None` — неизвестно.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileStat {
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStat {
+    fn modified_secs(&self) -> Option<u64> {
+        self.modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+    }
+}
+
+impl From<&std::fs::Metadata> for FileStat {
+    fn from(meta: &std::fs::Metadata) -> Self {
+        Self {
+            size: Some(meta.len()),
+            modified: meta.modified().ok(),
+        }
+    }
+}
+
+/// Файл пачки, который уже есть в месте назначения.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// Позиция файла в запросе.
+    pub index: usize,
+    pub name: String,
+    /// Передаваемый файл.
+    pub source: FileStat,
+    /// Файл, который будет перезаписан.
+    pub target: FileStat,
+}
+
+/// Первое свободное имя вида `stem (N).ext`; `taken` сообщает, занято ли имя.
+///
+/// Расширение сохраняется, чтобы файл открывался той же программой; точка
+/// в начале имени (`.profile`) расширение не отделяет.
+pub fn free_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let (stem, ext) = match name.rfind('.') {
+        Some(pos) if pos > 0 => name.split_at(pos),
+        _ => (name, ""),
+    };
+    (1..)
+        .map(|n| format!("{} ({}){}", stem, n, ext))
+        .find(|candidate| !taken(candidate))
+        .expect("бесконечный ряд кандидатов")
+}
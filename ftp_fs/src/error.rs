@@ -1,13 +1,19 @@
+use std::io::ErrorKind;
+
+use suppaftp::FtpError;
 use thiserror::Error;
 
+use crate::retry::RetryClass;
+
 /// Единый тип ошибки для всех операций `ftp_fs`.
 #[derive(Debug, Error)]
 pub enum FsError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// Ошибка FTP, которую не удалось отнести к более точному варианту.
     #[error("FTP error: {0}")]
-    Ftp(#[from] suppaftp::FtpError),
+    Ftp(#[from] FtpError),
 
     #[error("Connection timeout")]
     Timeout,
@@ -15,11 +21,189 @@ pub enum FsError {
     #[error("Not connected to any FTP server")]
     NotConnected,
 
-    #[error("Path not found: {0}")]
-    PathNotFound(String),
+    /// 550 от сервера или путь, которого нет в локальной ФС (`code == None`).
+    #[error("Path not found: {path}")]
+    PathNotFound { path: String, code: Option<u32> },
+
+    /// 550 с признаками запрета или выход за корень локальной ФС.
+    #[error("Permission denied: {path}")]
+    PermissionDenied { path: String, code: Option<u32> },
+
+    /// 553 от сервера или имя, отвергнутое ещё до отправки команды.
+    #[error("Invalid file name: {name:?}")]
+    InvalidFileName { name: String, code: Option<u32> },
+
+    #[error("Invalid netrc: {0}")]
+    Netrc(String),
+
+    /// 452, 552 — на сервере кончилось место или квота.
+    #[error("Storage quota exceeded: {path}")]
+    QuotaExceeded { path: String, code: u32 },
+
+    /// 450 — файл занят или временно недоступен.
+    #[error("File busy: {path}")]
+    FileBusy { path: String, code: u32 },
+
+    /// 530, 532 — сервер не принял учётные данные или сессия не авторизована.
+    #[error("Not logged in: {message}")]
+    NotLoggedIn { code: u32, message: String },
+
+    /// 425, 426 — не удалось открыть канал данных или он оборвался.
+    #[error("Data connection failed ({code}): {message}")]
+    DataConnection { code: u32, message: String },
+
+    /// 500, 502, 504 — сервер не знает команду или её параметр; без кода —
+    /// команда не пробовалась, потому что сервер её не объявил.
+    #[error("Command not supported: {message}")]
+    NotSupported { message: String, code: Option<u32> },
+
+    /// 421 — сервер закрывает управляющее соединение.
+    #[error("Service not available: {0}")]
+    ServiceClosing(String),
+
+    /// После передачи размер файла на сервере не совпал с переданным.
+    #[error("Size mismatch for {path}: server has {expected} bytes, transferred {actual}")]
+    SizeMismatch {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// Управляющее соединение оборвалось без ответа сервера.
+    #[error("Connection to the server lost")]
+    ConnectionLost,
+}
+
+impl FsError {
+    /// Разобрать ошибку `suppaftp` по коду ответа сервера.
+    ///
+    /// `path` — аргумент команды: он попадает в варианты, относящиеся к файлу.
+    /// Ответ 550 означает и «не найден», и «нет доступа»; различить их можно
+    /// только по тексту, поэтому без явных признаков запрета ответ
+    /// считается [`FsError::PathNotFound`].
+    pub fn from_ftp(err: FtpError, path: &str) -> Self {
+        let resp = match &err {
+            FtpError::UnexpectedResponse(resp) => resp,
+            FtpError::ConnectionError(io) if is_disconnect(io.kind()) => {
+                return Self::ConnectionLost;
+            }
+            // BadResponse — и ответ, который suppaftp не разобрал (SIZE не
+            // числом, PWD без кавычек), и пустая строка после закрытия
+            // соединения; закрытое соединение выдаст обрыв следующая команда
+            _ => return Self::Ftp(err),
+        };
+
+        let code = resp.status.code();
+        let message = reply_text(&String::from_utf8_lossy(&resp.body));
+        let path = path.to_string();
+        match code {
+            421 => Self::ServiceClosing(message),
+            425 | 426 => Self::DataConnection { code, message },
+            450 => Self::FileBusy { path, code },
+            452 | 552 => Self::QuotaExceeded { path, code },
+            500 | 502 | 504 => Self::NotSupported {
+                message,
+                code: Some(code),
+            },
+            530 | 532 => Self::NotLoggedIn { code, message },
+            550 if looks_denied(&message) => Self::PermissionDenied {
+                path,
+                code: Some(code),
+            },
+            550 => Self::PathNotFound {
+                path,
+                code: Some(code),
+            },
+            553 => Self::InvalidFileName {
+                name: path,
+                code: Some(code),
+            },
+            _ => Self::Ftp(err),
+        }
+    }
+
+    /// Код ответа FTP-сервера, если ошибка пришла от него.
+    pub fn reply_code(&self) -> Option<u32> {
+        match self {
+            Self::Ftp(FtpError::UnexpectedResponse(resp)) => Some(resp.status.code()),
+            Self::DataConnection { code, .. }
+            | Self::FileBusy { code, .. }
+            | Self::QuotaExceeded { code, .. }
+            | Self::NotLoggedIn { code, .. } => Some(*code),
+            Self::PathNotFound { code, .. }
+            | Self::PermissionDenied { code, .. }
+            | Self::InvalidFileName { code, .. }
+            | Self::NotSupported { code, .. } => *code,
+            Self::ServiceClosing(_) => Some(421),
+            _ => None,
+        }
+    }
+
+    /// Сессия больше не пригодна: нужно переподключиться
+    /// ([`crate::FtpFs::reconnect`]).
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(self, Self::ServiceClosing(_) | Self::ConnectionLost)
+    }
+
+    /// Класс временной ошибки для [`crate::RetryPolicy`]; `None` — повтор
+    /// не поможет.
+    ///
+    /// Ошибка ввода-вывода с признаками обрыва сети — это оборвавшийся
+    /// канал данных: управляющее соединение такие ошибки разбирает в
+    /// [`FsError::from_ftp`].
+    pub fn retry_class(&self) -> Option<RetryClass> {
+        match self {
+            Self::DataConnection { .. } => Some(RetryClass::DataConnection),
+            Self::Io(e) if is_disconnect(e.kind()) || e.kind() == ErrorKind::TimedOut => {
+                Some(RetryClass::DataConnection)
+            }
+            Self::FileBusy { .. } => Some(RetryClass::FileBusy),
+            Self::ServiceClosing(_) | Self::ConnectionLost => Some(RetryClass::ConnectionLost),
+            _ => None,
+        }
+    }
+}
+
+fn is_disconnect(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected
+    )
+}
+
+/// Текст ответа сервера без кодов в начале строк: `220-Hello\r\n220 Ready`
+/// превращается в `Hello\nReady`. Пустые строки отбрасываются.
+///
+/// Один разбор и для сообщений об ошибках, и для приветствия сервера, где
+/// переводы строк и отступы нужны для показа как есть.
+pub(crate) fn reply_text(reply: &str) -> String {
+    reply
+        .lines()
+        .map(|line| {
+            let line = line.trim_end();
+            match line.as_bytes() {
+                [a, b, c] | [a, b, c, b' ' | b'-', ..]
+                    if [a, b, c].iter().all(|d| d.is_ascii_digit()) =>
+                {
+                    line.get(4..).unwrap_or_default()
+                }
+                _ => line,
+            }
+        })
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    #[error("Permission denied: {0}")]
-    PermissionDenied(String),
+fn looks_denied(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    ["denied", "permission", "not allowed", "forbidden", "access"]
+        .iter()
+        .any(|word| message.contains(word))
 }
 
 /// Псевдоним результата с [`FsError`].
@@ -1,42 +1,183 @@
-use std::{str::FromStr, time::Duration};
+use std::{
+    collections::HashSet,
+    io::{ErrorKind, SeekFrom},
+    net::SocketAddr,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use async_trait::async_trait;
-use suppaftp::{list::File, tokio::AsyncFtpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use suppaftp::{FtpError, Mode, Status, list::File, tokio::AsyncFtpStream, types::FileType};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    net::ToSocketAddrs,
+    sync::mpsc,
+};
 
 use crate::{
     FileSystem,
-    error::{FsError, FsResult},
-    types::{FileEntry, FileKind, FtpConnectParams, TransferProgress},
+    capabilities::ServerCapabilities,
+    conflict::{Conflict, ConflictPolicy, FileStat, Resolution, free_name},
+    error::{FsError, FsResult, reply_text},
+    names::validate_file_names,
+    rate::RateLimiter,
+    retry::RetryPolicy,
+    types::{
+        CancelFlags, FileEntry, FileKind, FtpConnectParams, FtpMode, SkipReason, TransferEvent,
+        TransferOptions,
+    },
 };
 
+/// Размер блока при передаче файлов: между блоками сообщается прогресс.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Как часто проверять отмену файла во время паузы перед повтором.
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
 /// Реализация [`FileSystem`] для FTP-сервера через `suppaftp`.
 ///
-/// Хранит активное соединение [`AsyncFtpStream`].
+/// Хранит активное соединение [`AsyncFtpStream`], а также параметры,
+/// режим и текущую директорию — для [`FtpFs::reconnect`]. Что умеет
+/// сервер, выясняется при входе ([`FtpFs::capabilities`]).
 pub struct FtpFs {
     stream: AsyncFtpStream,
+    params: FtpConnectParams,
+    /// Адрес, проверенный вызывающей стороной ([`FtpFs::connect_to`]).
+    addr: Option<SocketAddr>,
+    mode: FtpMode,
+    cwd: Option<String>,
+    capabilities: ServerCapabilities,
+    /// Команды, которые сервер отверг как неизвестные (500, 502, 504):
+    /// до переподключения они больше не пробуются.
+    rejected: HashSet<&'static str>,
 }
 
 impl FtpFs {
     /// Установить FTP-соединение с заданными параметрами.
     ///
     /// Возвращает ошибку [`FsError::Timeout`] если сервер не ответил
-    /// за `params.timeout_secs` секунд, или [`FsError::Ftp`] при ошибке протокола.
+    /// за `params.timeout_secs` секунд, [`FsError::NotLoggedIn`] если он
+    /// не принял логин, или [`FsError::Ftp`] при прочих ошибках протокола.
     pub async fn connect(params: FtpConnectParams) -> FsResult<Self> {
         let addr = format!("{}:{}", params.host, params.port);
+        Self::connect_inner(addr.as_str(), None, params).await
+    }
+
+    /// Установить FTP-соединение с уже разрешённым адресом `addr`.
+    ///
+    /// Поля `host` и `port` из `params` не используются для подключения —
+    /// так вызывающая сторона может проверить адрес после DNS-разрешения
+    /// и подключиться именно к нему. Канал данных в пассивном режиме тоже
+    /// открывается только к `addr`: адрес из ответа PASV заменяется им, и
+    /// сервер не может направить клиента на другой хост.
+    pub async fn connect_to(addr: SocketAddr, params: FtpConnectParams) -> FsResult<Self> {
+        Self::connect_inner(addr, Some(addr), params).await
+    }
+
+    async fn connect_inner(
+        addr: impl ToSocketAddrs,
+        resolved: Option<SocketAddr>,
+        params: FtpConnectParams,
+    ) -> FsResult<Self> {
         let timeout = Duration::from_secs(params.timeout_secs);
 
-        let mut stream = tokio::time::timeout(timeout, AsyncFtpStream::connect(&addr))
+        let mut stream = tokio::time::timeout(timeout, AsyncFtpStream::connect(addr))
             .await
             .map_err(|_| FsError::Timeout)?
-            .map_err(FsError::Ftp)?;
+            .map_err(|e| FsError::from_ftp(e, &params.host))?;
+        // EPSV и так сообщает только порт, а адрес из PASV подменяется
+        // проверенным адресом управляющего соединения
+        stream.set_passive_nat_workaround(resolved.is_some());
 
         stream
             .login(&params.username, &params.password)
             .await
-            .map_err(FsError::Ftp)?;
+            .map_err(|e| FsError::from_ftp(e, &params.username))?;
+        // По умолчанию сессия в режиме ASCII, и сервер может менять концы
+        // строк: файлы передаются байт в байт только в двоичном режиме
+        stream
+            .transfer_type(FileType::Binary)
+            .await
+            .map_err(|e| FsError::from_ftp(e, &params.host))?;
+        let capabilities = detect_capabilities(&mut stream).await;
+
+        Ok(Self {
+            stream,
+            params,
+            addr: resolved,
+            mode: FtpMode::default(),
+            cwd: None,
+            capabilities,
+            rejected: HashSet::new(),
+        })
+    }
+
+    /// Логин, под которым выполнен вход.
+    pub fn username(&self) -> &str {
+        &self.params.username
+    }
+
+    /// Хост сервера, как он указан в параметрах подключения.
+    pub fn host(&self) -> &str {
+        &self.params.host
+    }
+
+    /// Что умеет сервер: приветствие, SYST и FEAT, полученные при входе.
+    pub fn capabilities(&self) -> &ServerCapabilities {
+        &self.capabilities
+    }
+
+    /// Стоит ли пробовать команду `command`; `advertised` — объявил ли её
+    /// сервер в FEAT. Без ответа на FEAT пробуется всё, что сервер ещё не
+    /// отверг.
+    fn allows(&self, command: &'static str, advertised: bool) -> bool {
+        !self.rejected.contains(command) && (advertised || !self.capabilities.feat)
+    }
+
+    /// Запомнить, если сервер ответил, что не знает команду `command`.
+    fn note<T>(&mut self, command: &'static str, result: &FsResult<T>) {
+        if let Err(FsError::NotSupported { .. }) = result {
+            self.rejected.insert(command);
+        }
+    }
+
+    /// Переподключиться с теми же параметрами, режимом и директорией.
+    ///
+    /// Нужен после ошибки, для которой [`FsError::is_connection_lost`]:
+    /// старая сессия при этом просто отбрасывается, без QUIT. Если прежнюю
+    /// директорию открыть не удалось, соединение остаётся в начальной.
+    pub async fn reconnect(&mut self) -> FsResult<()> {
+        let params = self.params.clone();
+        let mut fresh = match self.addr {
+            Some(addr) => Self::connect_to(addr, params).await?,
+            None => Self::connect(params).await?,
+        };
+        fresh.set_mode(self.mode);
+
+        if let Some(dir) = self.cwd.take()
+            && let Err(e) = fresh.change_dir(&dir).await
+        {
+            log::warn!(
+                "После переподключения не удалось вернуться в {}: {}",
+                dir,
+                e
+            );
+        }
+        *self = fresh;
+        Ok(())
+    }
 
-        Ok(Self { stream })
+    /// Выбрать режим канала данных для последующих передач и листингов.
+    pub fn set_mode(&mut self, mode: FtpMode) {
+        self.mode = mode;
+        self.stream.set_mode(match mode {
+            FtpMode::Passive => Mode::Passive,
+            FtpMode::ExtendedPassive => Mode::ExtendedPassive,
+            FtpMode::Active => Mode::Active,
+        });
     }
 
     /// Проверить живость соединения командой NOOP.
@@ -46,93 +187,944 @@ impl FtpFs {
         self.stream.noop().await.is_ok()
     }
 
+    /// Текущая директория на сервере (команда PWD).
+    ///
+    /// В отличие от [`FileSystem::current_dir`] требует `&mut self`,
+    /// потому что `suppaftp` отправляет команду через изменяемый поток.
+    pub async fn pwd(&mut self) -> FsResult<String> {
+        self.stream
+            .pwd()
+            .await
+            .map_err(|e| FsError::from_ftp(e, "."))
+    }
+
     /// Корректно завершить FTP-сессию командой QUIT.
     pub async fn disconnect(mut self) -> FsResult<()> {
-        self.stream.quit().await.map_err(FsError::Ftp)
+        self.stream
+            .quit()
+            .await
+            .map_err(|e| FsError::from_ftp(e, ""))
     }
 
     /// Загрузить файлы из локального пути на FTP-сервер.
     ///
-    /// Колбэк `on_progress` вызывается перед началом передачи каждого файла.
-    /// В будущем может вызываться на каждый chunk для прогресс-бара.
+    /// Ход передачи отправляется в `events` (см. [`TransferEvent`] о порядке
+    /// событий); получатель должен читать их параллельно с передачей —
+    /// канал ограничен и при переполнении передача ждёт. Канал закрывается,
+    /// когда передача заканчивается. Файл, отменённый через `cancel`,
+    /// пропускается, а недогруженная копия удаляется с сервера.
+    ///
+    /// Все имена проверяются [`validate_file_names`] до начала передачи —
+    /// это единственная ошибка, прерывающая всю пачку. Остальные ошибки
+    /// приходят событиями [`TransferEvent::Failed`]; директории и не обычные
+    /// файлы пропускаются. Временные ошибки повторяются по
+    /// `options.retry`: загрузка продолжается дописыванием (APPE) после
+    /// того, что сервер успел сохранить. Скорость ограничивают
+    /// `options.limiters`, файлы, уже лежащие на сервере, — политика
+    /// `options.conflict`.
+    ///
+    /// С `options.atomic_upload` файл загружается под временным именем и
+    /// переименовывается на место (RNFR/RNTO) только после проверки
+    /// размера: под настоящим именем никто не увидит недогруженный файл.
+    /// При ошибке или отмене временный файл удаляется.
     pub async fn upload(
         &mut self,
-        local_base: &std::path::Path,
+        local_base: &Path,
         filenames: &[String],
-        on_progress: impl Fn(TransferProgress) + Send,
+        options: &TransferOptions,
+        events: mpsc::Sender<TransferEvent>,
+        cancel: &CancelFlags,
     ) -> FsResult<()> {
-        for filename in filenames {
+        let filenames = validate_file_names(filenames)?;
+        // Без листинга конфликты не видны — файлы загружаются как есть
+        let listing = if options.checks_conflicts() {
+            self.list().await.unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let mut lost = false;
+        for (index, filename) in filenames.iter().enumerate() {
+            let name = filename.clone();
+            if lost {
+                emit(&events, skipped(index, name, SkipReason::Aborted)).await;
+                continue;
+            }
+
             let file_path = local_base.join(filename);
-            if !file_path.is_file() {
+            let meta = match tokio::fs::metadata(&file_path).await {
+                Ok(meta) => meta,
+                Err(e) => {
+                    let error = if e.kind() == ErrorKind::NotFound {
+                        FsError::PathNotFound {
+                            path: name.clone(),
+                            code: None,
+                        }
+                    } else {
+                        FsError::Io(e)
+                    };
+                    emit(&events, TransferEvent::Failed { index, name, error }).await;
+                    continue;
+                }
+            };
+            if meta.is_dir() {
+                emit(&events, skipped(index, name, SkipReason::Directory)).await;
                 continue;
             }
-            let size = file_path.metadata().ok().map(|m| m.len());
-            on_progress(TransferProgress {
-                filename: filename.clone(),
-                bytes_transferred: 0,
-                total_bytes: size,
-            });
-            let mut file = tokio::fs::File::open(&file_path)
+            if !meta.is_file() {
+                emit(&events, skipped(index, name, SkipReason::NotRegularFile)).await;
+                continue;
+            }
+            if cancel.is_cancelled(index) {
+                emit(&events, skipped(index, name, SkipReason::Cancelled)).await;
+                continue;
+            }
+
+            let mut remote_name = filename.clone();
+            if let Some(target) = listing.iter().find(|e| &e.name == filename && e.is_file()) {
+                let policy = options.conflict_for(filename);
+                let target = self.remote_stat(target, policy.needs_modified()).await;
+                let taken = |c: &str| {
+                    listing.iter().any(|e| e.name == c) || filenames.iter().any(|f| f == c)
+                };
+                let source = FileStat::from(&meta);
+                match resolve_conflict(index, filename, policy, &source, &target, taken, &events)
+                    .await
+                {
+                    Some(target) => remote_name = target,
+                    None => continue,
+                }
+            }
+
+            let total = Some(meta.len());
+            emit(&events, TransferEvent::Started { index, name, total }).await;
+            let temp_name = options
+                .atomic_upload
+                .as_ref()
+                .map(|p| p.apply(&remote_name));
+            let file = FileTransfer {
+                index,
+                is_upload: true,
+                name: filename,
+                filename: temp_name.as_deref().unwrap_or(&remote_name),
+                local: &file_path,
+                commit: temp_name.is_some().then_some(Commit::Remote(&remote_name)),
+                total,
+                limiters: &options.limiters,
+                events: &events,
+                cancel,
+            };
+            let started = Instant::now();
+            let result = self.transfer_file(&file, &options.retry).await;
+            lost = self.finish_file(&file, result, started).await;
+        }
+        Ok(())
+    }
+
+    /// Загрузить один файл; `Continue` — размер файла на сервере.
+    ///
+    /// `done` — сколько байт отправили прошлые попытки: если он не нулевой,
+    /// загрузка дописывает файл с того места, которое подтверждает SIZE.
+    async fn upload_one(
+        &mut self,
+        file: &FileTransfer<'_>,
+        done: &mut u64,
+    ) -> FsResult<ControlFlow<(), u64>> {
+        let filename = file.filename;
+        let mut local_file = tokio::fs::File::open(file.local)
+            .await
+            .map_err(FsError::Io)?;
+        // Отправленное прошлой попыткой сервер мог не успеть записать
+        let offset = if *done > 0 {
+            self.remote_size(filename)
                 .await
-                .map_err(FsError::Io)?;
-            let mut reader = BufReader::new(&mut file);
-            self.stream
-                .put_file(filename, &mut reader)
+                .map_or(0, |size| size.min(*done))
+        } else {
+            0
+        };
+        let mut data_stream = if offset > 0 {
+            local_file
+                .seek(SeekFrom::Start(offset))
                 .await
-                .map_err(FsError::Ftp)?;
+                .map_err(FsError::Io)?;
+            self.stream.append_with_stream(filename).await
+        } else {
+            self.stream.put_with_stream(filename).await
+        }
+        .map_err(|e| FsError::from_ftp(e, filename))?;
+        *done = offset;
+
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = match local_file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    self.discard_put(data_stream, filename).await?;
+                    return Err(FsError::Io(e));
+                }
+            };
+            if let Err(e) = data_stream.write_all(&buffer[..n]).await {
+                // Сервер закрыл канал данных — причина в его ответе (например, 552)
+                self.stream
+                    .finalize_put_stream(data_stream)
+                    .await
+                    .map_err(|e| FsError::from_ftp(e, filename))?;
+                return Err(FsError::Io(e));
+            }
+            *done += n as u64;
+            file.throttle(n).await;
+            file.progress(*done).await;
+            if file.is_cancelled() {
+                self.discard_put(data_stream, filename).await?;
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+
+        self.stream
+            .finalize_put_stream(data_stream)
+            .await
+            .map_err(|e| FsError::from_ftp(e, filename))?;
+        Ok(ControlFlow::Continue(*done))
+    }
+
+    /// Закрыть канал данных прерванной загрузки и удалить недогруженный файл.
+    ///
+    /// Ошибку возвращает, только если соединение потеряно.
+    async fn discard_put(
+        &mut self,
+        data_stream: impl AsyncWrite + Unpin,
+        filename: &str,
+    ) -> FsResult<()> {
+        if let Err(e) = self.stream.finalize_put_stream(data_stream).await {
+            let e = FsError::from_ftp(e, filename);
+            if e.is_connection_lost() {
+                return Err(e);
+            }
+        }
+        if let Err(e) = self.stream.rm(filename).await {
+            log::warn!("Не удалось удалить недогруженный {}: {}", filename, e);
         }
         Ok(())
     }
 
     /// Скачать файлы с FTP-сервера в локальный путь.
     ///
-    /// Директории и другие элементы, для которых `retr` выбросит ошибку, пропускаются.
+    /// Директории определяются по листингу текущей директории и
+    /// пропускаются. Файл скачивается во временный `<имя>.part` рядом,
+    /// сбрасывается на диск и занимает своё место только
+    /// после успешной передачи и проверки размера — оборванное скачивание
+    /// не оставляет обрезанный файл под настоящим именем и не портит
+    /// прежний.
+    ///
+    /// События, отмена и повторы — как в [`FtpFs::upload`]; при отмене или
+    /// ошибке временный файл удаляется, а повтор продолжает его дописывать
+    /// с места обрыва командой REST, если сервер её принимает. Существующие
+    /// локальные файлы — по политике `options.conflict`.
     pub async fn download(
         &mut self,
-        local_base: &std::path::Path,
+        local_base: &Path,
         filenames: &[String],
-        on_progress: impl Fn(TransferProgress) + Send,
+        options: &TransferOptions,
+        events: mpsc::Sender<TransferEvent>,
+        cancel: &CancelFlags,
     ) -> FsResult<()> {
-        for filename in filenames {
-            on_progress(TransferProgress {
-                filename: filename.clone(),
-                bytes_transferred: 0,
-                total_bytes: None,
+        let filenames = validate_file_names(filenames)?;
+        // Без листинга всё равно пробуем RETR — ошибка попадёт в события
+        let listing = self.list().await.unwrap_or_default();
+        let mut lost = false;
+        for (index, filename) in filenames.iter().enumerate() {
+            let name = filename.clone();
+            if lost {
+                emit(&events, skipped(index, name, SkipReason::Aborted)).await;
+                continue;
+            }
+
+            let entry = listing.iter().find(|e| &e.name == filename);
+            if entry.is_some_and(|e| e.kind == FileKind::Directory) {
+                emit(&events, skipped(index, name, SkipReason::Directory)).await;
+                continue;
+            }
+            if cancel.is_cancelled(index) {
+                emit(&events, skipped(index, name, SkipReason::Cancelled)).await;
+                continue;
+            }
+
+            let mut file_path = local_base.join(filename);
+            if options.checks_conflicts()
+                && let Ok(meta) = tokio::fs::metadata(&file_path).await
+                && meta.is_file()
+            {
+                let policy = options.conflict_for(filename);
+                let source = match entry {
+                    Some(entry) => self.remote_stat(entry, policy.needs_modified()).await,
+                    None => FileStat::default(),
+                };
+                let taken =
+                    |c: &str| local_base.join(c).exists() || filenames.iter().any(|f| f == c);
+                let target = FileStat::from(&meta);
+                match resolve_conflict(index, filename, policy, &source, &target, taken, &events)
+                    .await
+                {
+                    Some(target) => file_path = local_base.join(target),
+                    None => continue,
+                }
+            }
+
+            let total = entry.and_then(|e| e.size);
+            emit(&events, TransferEvent::Started { index, name, total }).await;
+            let part = part_path(&file_path);
+            let file = FileTransfer {
+                index,
+                is_upload: false,
+                name: filename,
+                filename,
+                local: &part,
+                commit: Some(Commit::Local(&file_path)),
+                total,
+                limiters: &options.limiters,
+                events: &events,
+                cancel,
+            };
+            let started = Instant::now();
+            let result = self.transfer_file(&file, &options.retry).await;
+            lost = self.finish_file(&file, result, started).await;
+        }
+        Ok(())
+    }
+
+    /// Файлы пачки, которые уже есть в месте назначения, — чтобы спросить
+    /// пользователя, что с ними делать, до начала передачи.
+    ///
+    /// `is_upload` — направление: при загрузке ищутся файлы на сервере,
+    /// при скачивании — в `local_base`. Время изменения файлов на сервере
+    /// запрашивается командой MDTM.
+    pub async fn find_conflicts(
+        &mut self,
+        local_base: &Path,
+        filenames: &[String],
+        is_upload: bool,
+    ) -> FsResult<Vec<Conflict>> {
+        let filenames = validate_file_names(filenames)?;
+        let listing = self.list().await?;
+        let mut conflicts = Vec::new();
+        for (index, name) in filenames.into_iter().enumerate() {
+            let Some(entry) = listing.iter().find(|e| e.name == name && e.is_file()) else {
+                continue;
+            };
+            let Ok(meta) = tokio::fs::metadata(local_base.join(&name)).await else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
+            }
+            let local = FileStat::from(&meta);
+            let remote = self.remote_stat(entry, true).await;
+            let (source, target) = if is_upload {
+                (local, remote)
+            } else {
+                (remote, local)
+            };
+            conflicts.push(Conflict {
+                index,
+                name,
+                source,
+                target,
             });
-            match self.stream.retr_as_stream(filename).await {
-                Ok(mut data_stream) => {
-                    let file_path = local_base.join(filename);
-                    if let Ok(mut local_file) = tokio::fs::File::create(&file_path).await {
-                        let mut buffer = [0u8; 8192];
-                        loop {
-                            let n = data_stream.read(&mut buffer).await.map_err(FsError::Io)?;
-                            if n == 0 {
-                                break;
-                            }
-                            local_file
-                                .write_all(&buffer[..n])
-                                .await
-                                .map_err(FsError::Io)?;
-                        }
-                    }
-                    self.stream
-                        .finalize_retr_stream(data_stream)
-                        .await
-                        .map_err(FsError::Ftp)?;
+        }
+        Ok(conflicts)
+    }
+
+    /// Размер файла из листинга и, если `with_modified`, время его
+    /// изменения на сервере.
+    async fn remote_stat(&mut self, entry: &FileEntry, with_modified: bool) -> FileStat {
+        let modified = if with_modified {
+            self.modified(&entry.name).await
+        } else {
+            None
+        };
+        FileStat {
+            size: entry.size,
+            modified,
+        }
+    }
+
+    /// Размер файла на сервере (SIZE).
+    async fn remote_size(&mut self, filename: &str) -> FsResult<u64> {
+        if !self.allows("SIZE", self.capabilities.size) {
+            return Err(FsError::NotSupported {
+                message: "SIZE".to_string(),
+                code: None,
+            });
+        }
+        let result = self
+            .stream
+            .size(filename)
+            .await
+            .map(|size| size as u64)
+            .map_err(|e| FsError::from_ftp(e, filename));
+        self.note("SIZE", &result);
+        result
+    }
+
+    /// Продолжить следующее скачивание `filename` с байта `offset` (REST).
+    async fn resume(&mut self, filename: &str, offset: u64) -> FsResult<()> {
+        if !self.allows("REST", self.capabilities.rest_stream) {
+            return Err(FsError::NotSupported {
+                message: "REST STREAM".to_string(),
+                code: None,
+            });
+        }
+        let result = self
+            .stream
+            .resume_transfer(offset as usize)
+            .await
+            .map_err(|e| FsError::from_ftp(e, filename));
+        self.note("REST", &result);
+        result
+    }
+
+    /// Время изменения файла на сервере: MDTM, а если сервер её не знает —
+    /// MLST; `None`, если сервер его не сообщил.
+    async fn modified(&mut self, filename: &str) -> Option<SystemTime> {
+        let mut result = Err(FsError::NotSupported {
+            message: "MDTM".to_string(),
+            code: None,
+        });
+        if self.allows("MDTM", self.capabilities.mdtm) {
+            result = self
+                .stream
+                .mdtm(filename)
+                .await
+                .map_err(|e| FsError::from_ftp(e, filename));
+            self.note("MDTM", &result);
+        }
+        if matches!(result, Err(FsError::NotSupported { .. }))
+            && self.allows("MLST", self.capabilities.mlsd)
+        {
+            result = match self.stream.mlst(Some(filename)).await {
+                Ok(facts) => mlst_modify(&facts).ok_or(FsError::Ftp(FtpError::BadResponse)),
+                Err(e) => Err(FsError::from_ftp(e, filename)),
+            };
+            self.note("MLST", &result);
+        }
+        match result {
+            Ok(time) => {
+                let secs = u64::try_from(time.and_utc().timestamp()).ok()?;
+                Some(UNIX_EPOCH + Duration::from_secs(secs))
+            }
+            Err(e) => {
+                log::debug!("Время изменения {} неизвестно: {}", filename, e);
+                None
+            }
+        }
+    }
+
+    /// Установить время изменения файла на сервере: MFMT, а если сервер её
+    /// не знает — SITE UTIME. Если не сработало ни то, ни другое, время
+    /// остаётся тем, что поставил сервер.
+    async fn set_modified(&mut self, filename: &str, time: SystemTime) -> FsResult<()> {
+        let stamp = DateTime::<Utc>::from(time).format("%Y%m%d%H%M%S");
+        if self.allows("MFMT", self.capabilities.mfmt) {
+            let command = format!("MFMT {} {}", stamp, filename);
+            let result = self
+                .stream
+                .custom_command(command, &[Status::File])
+                .await
+                .map(drop)
+                .map_err(|e| FsError::from_ftp(e, filename));
+            self.note("MFMT", &result);
+            if !matches!(result, Err(FsError::NotSupported { .. })) {
+                return result;
+            }
+        }
+        // SITE-команды в FEAT не перечисляются: пробуем, пока сервер не
+        // ответит, что не знает её
+        if self.allows(SITE_UTIME, true) {
+            let command = format!("UTIME {} {} {} {} UTC", filename, stamp, stamp, stamp);
+            let result = self
+                .stream
+                .site(command)
+                .await
+                .map(drop)
+                .map_err(|e| FsError::from_ftp(e, filename));
+            self.note(SITE_UTIME, &result);
+            if !matches!(result, Err(FsError::NotSupported { .. })) {
+                return result;
+            }
+        }
+        log::debug!("Сервер не умеет менять время изменения {}", filename);
+        Ok(())
+    }
+
+    /// Перенести время изменения источника на переданный файл, чтобы
+    /// сравнение по времени ([`ConflictPolicy::OverwriteIfNewer`]) видело
+    /// настоящий возраст содержимого, а не момент передачи. Неудача
+    /// передачу не портит — только пишется в лог.
+    async fn keep_modified(&mut self, file: &FileTransfer<'_>) {
+        let result = if file.is_upload {
+            let Ok(time) = tokio::fs::metadata(file.local)
+                .await
+                .and_then(|m| m.modified())
+            else {
+                return;
+            };
+            self.set_modified(file.filename, time).await
+        } else {
+            let Some(time) = self.modified(file.filename).await else {
+                return;
+            };
+            set_local_modified(file.local, time).await
+        };
+        if let Err(e) = result {
+            log::warn!("Не удалось сохранить время изменения {}: {}", file.name, e);
+        }
+    }
+
+    /// Скачать один файл; `Continue` — размер скачанного файла.
+    ///
+    /// Данные пишутся в `file.local` — временный файл. `done` — сколько байт
+    /// прошлые попытки записали в него: скачивание продолжается с этого
+    /// места, если сервер принимает REST.
+    async fn download_one(
+        &mut self,
+        file: &FileTransfer<'_>,
+        done: &mut u64,
+    ) -> FsResult<ControlFlow<(), u64>> {
+        let filename = file.filename;
+        // Файл открывается до REST и RETR: ошибка создания не оставляет на
+        // сервере ни сдвига REST, ни открытого канала данных
+        let mut local_file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(file.local)
+            .await
+            .map_err(FsError::Io)?;
+        let mut offset = *done;
+        // Хвост после `done` прошлая попытка могла записать не целиком
+        local_file.set_len(offset).await.map_err(FsError::Io)?;
+        local_file
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(FsError::Io)?;
+        if offset > 0
+            && let Err(e) = self.resume(filename, offset).await
+        {
+            if e.is_connection_lost() {
+                return Err(e);
+            }
+            log::debug!("{}: REST не принят, скачивание заново: {}", filename, e);
+            offset = 0;
+            local_file.set_len(0).await.map_err(FsError::Io)?;
+            local_file.rewind().await.map_err(FsError::Io)?;
+        }
+        let mut data_stream = self
+            .stream
+            .retr_as_stream(filename)
+            .await
+            .map_err(|e| FsError::from_ftp(e, filename))?;
+        *done = offset;
+
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = match data_stream.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    // Повтор продолжит с `done`: всё полученное должно быть на диске
+                    let _ = local_file.flush().await;
+                    self.discard_retr(data_stream, filename).await?;
+                    return Err(FsError::Io(e));
                 }
-                Err(_) => {
-                    // Директории или недоступные файлы — пропускаем
+            };
+            if let Err(e) = local_file.write_all(&buffer[..n]).await {
+                self.discard_retr(data_stream, filename).await?;
+                return Err(FsError::Io(e));
+            }
+            *done += n as u64;
+            file.throttle(n).await;
+            file.progress(*done).await;
+            if file.is_cancelled() {
+                self.discard_retr(data_stream, filename).await?;
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        local_file.flush().await.map_err(FsError::Io)?;
+        // До переименования данные должны быть на диске, иначе после сбоя
+        // питания под настоящим именем может оказаться пустой файл
+        local_file.sync_all().await.map_err(FsError::Io)?;
+
+        self.stream
+            .finalize_retr_stream(data_stream)
+            .await
+            .map_err(|e| FsError::from_ftp(e, filename))?;
+        Ok(ControlFlow::Continue(*done))
+    }
+
+    /// Закрыть канал данных, не дочитав его, и прочитать итоговый ответ.
+    ///
+    /// Сервер ответит 426 или 226 — оба годятся; ошибку возвращает, только
+    /// если соединение потеряно.
+    async fn discard_retr(&mut self, data_stream: impl AsyncRead, filename: &str) -> FsResult<()> {
+        match self.stream.finalize_retr_stream(data_stream).await {
+            Err(e) => {
+                let e = FsError::from_ftp(e, filename);
+                if e.is_connection_lost() {
+                    Err(e)
+                } else {
+                    Ok(())
                 }
             }
+            Ok(()) => Ok(()),
         }
-        Ok(())
+    }
+
+    /// Передать файл, повторяя временные ошибки по `retry`; `Continue` —
+    /// размер переданного файла.
+    ///
+    /// Перед повтором после обрыва управляющего соединения выполняется
+    /// [`FtpFs::reconnect`]; если он не удался, файл завершается
+    /// [`FsError::ConnectionLost`] и пачка прерывается.
+    async fn transfer_file(
+        &mut self,
+        file: &FileTransfer<'_>,
+        retry: &RetryPolicy,
+    ) -> FsResult<ControlFlow<(), u64>> {
+        // Сколько байт уже в месте назначения: с него продолжает повтор
+        let mut done = 0u64;
+        let mut attempt = 1;
+        loop {
+            let result = if file.is_upload {
+                self.upload_one(file, &mut done).await
+            } else {
+                self.download_one(file, &mut done).await
+            };
+            let error = match result {
+                Err(error) if retry.should_retry(&error, attempt) => error,
+                result => return result,
+            };
+
+            let delay = retry.backoff(attempt);
+            attempt += 1;
+            let lost = error.is_connection_lost();
+            log::info!(
+                "{}: {}; попытка {} через {:?}",
+                file.filename,
+                error,
+                attempt,
+                delay
+            );
+            emit(
+                file.events,
+                TransferEvent::Retrying {
+                    index: file.index,
+                    attempt,
+                    delay,
+                    error,
+                },
+            )
+            .await;
+            if !wait_unless_cancelled(delay, file.index, file.cancel).await {
+                return Ok(ControlFlow::Break(()));
+            }
+            if lost && let Err(e) = self.reconnect().await {
+                log::warn!(
+                    "Не удалось переподключиться для повтора {}: {}",
+                    file.filename,
+                    e
+                );
+                return Err(FsError::ConnectionLost);
+            }
+        }
+    }
+
+    /// Отправить завершающее событие файла, проверив размер переданного.
+    ///
+    /// Возвращает `true`, если соединение потеряно и пачку пора прервать.
+    async fn finish_file(
+        &mut self,
+        file: &FileTransfer<'_>,
+        result: FsResult<ControlFlow<(), u64>>,
+        started: Instant,
+    ) -> bool {
+        let (index, filename, events) = (file.index, file.filename, file.events);
+        let name = file.name.to_string();
+        let bytes = match result {
+            Ok(ControlFlow::Continue(bytes)) => bytes,
+            Ok(ControlFlow::Break(())) => {
+                self.discard_temp(file).await;
+                emit(events, skipped(index, name, SkipReason::Cancelled)).await;
+                return false;
+            }
+            Err(error) => {
+                self.discard_temp(file).await;
+                let lost = error.is_connection_lost();
+                emit(events, TransferEvent::Failed { index, name, error }).await;
+                return lost;
+            }
+        };
+        let duration = started.elapsed();
+
+        // SIZE поддерживают не все серверы: скачанное тогда сверяется с
+        // размером из листинга, а если неизвестен и он — файл, как и
+        // загруженный, встаёт на место без проверки
+        let mut lost = false;
+        let expected = match self.remote_size(filename).await {
+            Ok(size) => Some(size),
+            Err(e) => {
+                log::debug!("Размер {} по SIZE не проверен: {}", filename, e);
+                // Файл уже целиком получен: обрыв прервёт пачку после него
+                lost = e.is_connection_lost();
+                if file.is_upload { None } else { file.total }
+            }
+        };
+        match expected {
+            Some(size) if size == bytes => {
+                emit(events, TransferEvent::Verified { index, size: bytes }).await;
+            }
+            Some(size) => {
+                self.discard_temp(file).await;
+                let error = FsError::SizeMismatch {
+                    path: name.clone(),
+                    expected: size,
+                    actual: bytes,
+                };
+                emit(events, TransferEvent::Failed { index, name, error }).await;
+                return false;
+            }
+            None => {}
+        }
+        // До переименования: оно время изменения не трогает
+        if !lost {
+            self.keep_modified(file).await;
+        }
+        if let Err(error) = self.commit(file).await {
+            self.discard_temp(file).await;
+            let lost = error.is_connection_lost();
+            emit(events, TransferEvent::Failed { index, name, error }).await;
+            return lost;
+        }
+        emit(
+            events,
+            TransferEvent::Completed {
+                index,
+                name,
+                bytes,
+                duration,
+            },
+        )
+        .await;
+        lost
+    }
+
+    /// Переместить файл, переданный под временным именем, на место.
+    async fn commit(&mut self, file: &FileTransfer<'_>) -> FsResult<()> {
+        match file.commit {
+            None => Ok(()),
+            Some(Commit::Local(destination)) => tokio::fs::rename(file.local, destination)
+                .await
+                .map_err(FsError::Io),
+            Some(Commit::Remote(target)) => self
+                .stream
+                .rename(file.filename, target)
+                .await
+                .map_err(|e| FsError::from_ftp(e, target)),
+        }
+    }
+
+    /// Удалить временный файл неудавшейся передачи, если он есть.
+    async fn discard_temp(&mut self, file: &FileTransfer<'_>) {
+        let result = match file.commit {
+            None => return,
+            Some(Commit::Local(_)) => tokio::fs::remove_file(file.local)
+                .await
+                .map_err(FsError::Io),
+            Some(Commit::Remote(_)) => self
+                .stream
+                .rm(file.filename)
+                .await
+                .map_err(|e| FsError::from_ftp(e, file.filename)),
+        };
+        match result {
+            Ok(()) | Err(FsError::PathNotFound { .. }) => {}
+            Err(FsError::Io(e)) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Не удалось удалить временный {}: {}", file.filename, e),
+        }
+    }
+}
+
+/// Команда установки времени изменения, если сервер не знает MFMT.
+const SITE_UTIME: &str = "SITE UTIME";
+
+/// Выяснить, что умеет сервер: приветствие, SYST и FEAT. Если сервер не
+/// знает SYST или FEAT, соответствующие поля остаются пустыми.
+async fn detect_capabilities(stream: &mut AsyncFtpStream) -> ServerCapabilities {
+    let mut capabilities = match stream.feat().await {
+        Ok(features) => ServerCapabilities::from_features(features),
+        Err(e) => {
+            log::debug!("Сервер не сообщил свои возможности (FEAT): {}", e);
+            ServerCapabilities::default()
+        }
+    };
+    capabilities.welcome = stream.get_welcome_msg().map(reply_text);
+    capabilities.system = match stream.custom_command("SYST", &[Status::Name]).await {
+        Ok(reply) => reply.as_string().ok().map(|text| reply_text(&text)),
+        Err(e) => {
+            log::debug!("Сервер не сообщил тип системы (SYST): {}", e);
+            None
+        }
+    };
+    capabilities
+}
+
+/// Время изменения из ответа MLST: факт `modify=YYYYMMDDHHMMSS[.sss]`.
+fn mlst_modify(facts: &str) -> Option<NaiveDateTime> {
+    let (facts, _name) = facts.split_once(' ')?;
+    let value = facts.split(';').find_map(|fact| {
+        let (key, value) = fact.split_once('=')?;
+        key.eq_ignore_ascii_case("modify").then_some(value)
+    })?;
+    NaiveDateTime::parse_from_str(value.get(..14)?, "%Y%m%d%H%M%S").ok()
+}
+
+/// Установить время изменения локального файла.
+async fn set_local_modified(path: &Path, time: SystemTime) -> FsResult<()> {
+    let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    file.into_std().await.set_modified(time)?;
+    Ok(())
+}
+
+/// Куда переместить файл, переданный под временным именем, после проверки.
+#[derive(Clone, Copy)]
+enum Commit<'a> {
+    /// Скачанный `.part` — на место локального файла.
+    Local(&'a Path),
+    /// Загруженный под временным именем — на это имя на сервере.
+    Remote(&'a str),
+}
+
+/// Передаваемый файл и куда сообщать о ходе его передачи.
+struct FileTransfer<'a> {
+    index: usize,
+    is_upload: bool,
+    /// Запрошенное имя: под ним файл приходит в событиях.
+    name: &'a str,
+    /// Имя файла на сервере; при атомарной загрузке — временное.
+    filename: &'a str,
+    /// Путь к локальному файлу; при скачивании — временному.
+    local: &'a Path,
+    /// Куда переместить файл после проверки; `None` — он передаётся сразу
+    /// под настоящим именем.
+    commit: Option<Commit<'a>>,
+    /// Размер файла: при скачивании — из листинга, при загрузке — локальный.
+    total: Option<u64>,
+    limiters: &'a [RateLimiter],
+    events: &'a mpsc::Sender<TransferEvent>,
+    cancel: &'a CancelFlags,
+}
+
+impl FileTransfer<'_> {
+    /// Учесть переданный блок во всех ограничителях скорости.
+    async fn throttle(&self, bytes: usize) {
+        for limiter in self.limiters {
+            limiter.acquire(bytes as u64).await;
+        }
+    }
+
+    async fn progress(&self, bytes: u64) {
+        let event = TransferEvent::Progress {
+            index: self.index,
+            bytes,
+            total: self.total,
+        };
+        emit(self.events, event).await;
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled(self.index)
+    }
+}
+
+/// Временный файл, в который скачивается `path`: `<имя>.part` рядом с ним.
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Подождать `delay` перед повтором; `false` — файл отменили раньше.
+async fn wait_unless_cancelled(delay: Duration, index: usize, cancel: &CancelFlags) -> bool {
+    let deadline = tokio::time::Instant::now() + delay;
+    while !cancel.is_cancelled(index) {
+        let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        tokio::time::sleep(left.min(CANCEL_POLL)).await;
+    }
+    false
+}
+
+/// Применить `policy` к файлу, на месте которого в месте назначения уже
+/// лежит `target`; `taken` сообщает, занято ли имя для переименования.
+///
+/// Возвращает имя, под которым файл сохранить, или `None`, если файл
+/// пропущен — событие об этом уже отправлено.
+async fn resolve_conflict(
+    index: usize,
+    name: &str,
+    policy: ConflictPolicy,
+    source: &FileStat,
+    target: &FileStat,
+    taken: impl Fn(&str) -> bool,
+    events: &mpsc::Sender<TransferEvent>,
+) -> Option<String> {
+    match policy.resolve(source, target) {
+        Resolution::Transfer => Some(name.to_string()),
+        Resolution::Skip(reason) => {
+            log::info!("{}: уже есть в месте назначения, пропущен", name);
+            emit(events, skipped(index, name.to_string(), reason)).await;
+            None
+        }
+        Resolution::Rename => {
+            let target = free_name(name, taken);
+            log::info!(
+                "{}: уже есть в месте назначения, сохраняется как {}",
+                name,
+                target
+            );
+            let event = TransferEvent::Renamed {
+                index,
+                target: target.clone(),
+            };
+            emit(events, event).await;
+            Some(target)
+        }
+    }
+}
+
+/// Отправить событие. Получатель мог перестать слушать — передача от этого
+/// не прерывается.
+async fn emit(events: &mpsc::Sender<TransferEvent>, event: TransferEvent) {
+    let _ = events.send(event).await;
+}
+
+fn skipped(index: usize, name: String, reason: SkipReason) -> TransferEvent {
+    TransferEvent::Skipped {
+        index,
+        name,
+        reason,
     }
 }
 
 #[async_trait]
 impl FileSystem for FtpFs {
     async fn list(&mut self) -> FsResult<Vec<FileEntry>> {
-        let raw = self.stream.list(None).await.map_err(FsError::Ftp)?;
+        let raw = self
+            .stream
+            .list(None)
+            .await
+            .map_err(|e| FsError::from_ftp(e, "."))?;
         let mut files: Vec<FileEntry> = raw
             .into_iter()
             .flat_map(|line| File::from_str(&line))
@@ -162,10 +1154,15 @@ impl FileSystem for FtpFs {
 
     async fn change_dir(&mut self, path: &str) -> FsResult<()> {
         if path == ".." {
-            self.stream.cdup().await.map_err(FsError::Ftp)
+            self.stream.cdup().await
         } else {
-            self.stream.cwd(path).await.map_err(FsError::Ftp)
+            self.stream.cwd(path).await
         }
+        .map_err(|e| FsError::from_ftp(e, path))?;
+
+        // Запомнить абсолютный путь, чтобы вернуться в него после reconnect
+        self.cwd = self.stream.pwd().await.ok();
+        Ok(())
     }
 
     async fn current_dir(&self) -> FsResult<String> {
@@ -3,15 +3,30 @@
 //! Предоставляет единый трейт [`FileSystem`] и конкретные реализации
 //! [`LocalFs`] и [`FtpFs`], а также [`TransferEngine`] для передачи файлов.
 
+pub mod capabilities;
+pub mod conflict;
 pub mod error;
 pub mod local;
 pub mod ftp;
+pub mod names;
+pub mod netrc;
+pub mod rate;
+pub mod retry;
 pub mod types;
 
+pub use capabilities::ServerCapabilities;
+pub use conflict::{Conflict, ConflictPolicy, FileStat, Resolution, free_name};
 pub use error::{FsError, FsResult};
-pub use types::{FileEntry, FileKind, FtpConnectParams, TransferProgress};
+pub use types::{
+    CancelFlags, FileEntry, FileKind, FtpConnectParams, FtpMode, SkipReason, TransferEvent,
+    TransferItem, TransferOptions, TransferOutcome, TransferReport, format_size,
+};
 pub use local::LocalFs;
 pub use ftp::FtpFs;
+pub use names::{TempNamePattern, validate_file_name, validate_file_names};
+pub use netrc::{Netrc, NetrcEntry};
+pub use rate::RateLimiter;
+pub use retry::{RetryClass, RetryPolicy};
 
 use async_trait::async_trait;
 
@@ -11,21 +11,73 @@ use crate::{
 
 /// Реализация [`FileSystem`] для локальной файловой системы.
 ///
-/// Хранит текущий путь навигации и реализует листинг, смену директории.
+/// Навигация ограничена корнем `root`: ни `..`, ни абсолютные пути,
+/// ни символические ссылки не позволяют выйти за его пределы.
+/// Каждому пользователю можно выдать отдельный экземпляр со своим корнем.
 pub struct LocalFs {
+    root: PathBuf,
     current_path: PathBuf,
 }
 
 impl LocalFs {
-    /// Создать `LocalFs` с начальным путём.
-    pub fn new(path: PathBuf) -> Self {
-        Self { current_path: path }
+    /// Создать `LocalFs` с корнем `root`. Начальная директория — сам корень.
+    ///
+    /// Возвращает [`FsError::Io`] если корень не существует.
+    pub fn new(root: PathBuf) -> FsResult<Self> {
+        let root = root.canonicalize().map_err(FsError::Io)?;
+        if !root.is_dir() {
+            return Err(FsError::PathNotFound {
+                path: root.to_string_lossy().to_string(),
+                code: None,
+            });
+        }
+        Ok(Self {
+            current_path: root.clone(),
+            root,
+        })
+    }
+
+    /// Корень песочницы (канонический абсолютный путь).
+    pub fn root(&self) -> &Path {
+        &self.root
     }
 
-    /// Текущий путь как `&Path`.
+    /// Текущий путь как `&Path` (абсолютный, внутри корня).
     pub fn path(&self) -> &Path {
         &self.current_path
     }
+
+    /// Разрешить `path` относительно текущей директории.
+    ///
+    /// Пути, начинающиеся с `/`, отсчитываются от корня. Результат
+    /// канонизируется (раскрываются `..` и символические ссылки) и должен
+    /// остаться внутри корня, иначе возвращается [`FsError::PermissionDenied`].
+    pub fn resolve(&self, path: &str) -> FsResult<PathBuf> {
+        self.resolve_from(&self.current_path, path)
+    }
+
+    /// Как [`LocalFs::resolve`], но относительно директории `dir` вместо
+    /// текущей — например, директории, из которой передавалась пачка.
+    pub fn resolve_from(&self, dir: &Path, path: &str) -> FsResult<PathBuf> {
+        let candidate = match path.strip_prefix('/') {
+            Some(rest) => self.root.join(rest),
+            None => dir.join(path),
+        };
+        let resolved = candidate
+            .canonicalize()
+            .map_err(|_| FsError::PathNotFound {
+                path: path.to_string(),
+                code: None,
+            })?;
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(FsError::PermissionDenied {
+                path: path.to_string(),
+                code: None,
+            })
+        }
+    }
 }
 
 #[async_trait]
@@ -53,24 +105,33 @@ impl FileSystem for LocalFs {
     }
 
     async fn change_dir(&mut self, path: &str) -> FsResult<()> {
-        let new_path = if path == ".." {
-            self.current_path
-                .parent()
-                .map(|p| p.to_path_buf())
-                .unwrap_or_else(|| self.current_path.clone())
-        } else {
-            self.current_path.join(path)
-        };
+        // `..` в корне — не ошибка, просто остаёмся на месте
+        if path == ".." && self.current_path == self.root {
+            return Ok(());
+        }
 
+        let new_path = self.resolve(path)?;
         if new_path.is_dir() {
             self.current_path = new_path;
             Ok(())
         } else {
-            Err(FsError::PathNotFound(path.to_string()))
+            Err(FsError::PathNotFound {
+                path: path.to_string(),
+                code: None,
+            })
         }
     }
 
     async fn current_dir(&self) -> FsResult<String> {
-        Ok(self.current_path.to_string_lossy().to_string())
+        // Путь отображается относительно корня: "/", "/docs/2024"
+        let relative = self
+            .current_path
+            .strip_prefix(&self.root)
+            .unwrap_or(Path::new(""));
+        let parts: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        Ok(format!("/{}", parts.join("/")))
     }
 }
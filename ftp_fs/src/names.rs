@@ -0,0 +1,86 @@
+use std::path::{Component, Path};
+
+use serde::Deserialize;
+
+use crate::error::{FsError, FsResult};
+
+/// Проверить и нормализовать имя файла, пришедшее извне (формы, API).
+///
+/// Имя должно быть ровно одним компонентом пути внутри текущей директории:
+/// отклоняются пустые имена, `.` и `..`, абсолютные пути, разделители `/` и `\`,
+/// а также управляющие символы (в том числе `\r\n`, которые позволили бы
+/// внедрить команду в управляющее FTP-соединение).
+///
+/// Возвращает нормализованное имя или [`FsError::InvalidFileName`].
+pub fn validate_file_name(name: &str) -> FsResult<String> {
+    let invalid = || FsError::InvalidFileName {
+        name: name.to_string(),
+        code: None,
+    };
+
+    if name.is_empty() || name.contains(['/', '\\']) || name.chars().any(char::is_control) {
+        return Err(invalid());
+    }
+
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(part)), None) => {
+            part.to_str().map(str::to_string).ok_or_else(invalid)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Проверить набор имён целиком: первая же ошибка отклоняет весь набор.
+pub fn validate_file_names(names: &[String]) -> FsResult<Vec<String>> {
+    names.iter().map(|n| validate_file_name(n)).collect()
+}
+
+/// Шаблон временного имени для атомарной загрузки: `{name}` заменяется
+/// именем файла. По умолчанию `.{name}.uploading`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TempNamePattern(String);
+
+impl TempNamePattern {
+    const PLACEHOLDER: &'static str = "{name}";
+
+    /// Проверить шаблон: он должен содержать `{name}` и давать допустимое
+    /// имя файла, отличное от исходного.
+    pub fn new(pattern: impl Into<String>) -> FsResult<Self> {
+        let pattern = pattern.into();
+        let valid = pattern.contains(Self::PLACEHOLDER)
+            && pattern != Self::PLACEHOLDER
+            && validate_file_name(&pattern.replace(Self::PLACEHOLDER, "file")).is_ok();
+        if valid {
+            Ok(Self(pattern))
+        } else {
+            Err(FsError::InvalidFileName {
+                name: pattern,
+                code: None,
+            })
+        }
+    }
+
+    /// Временное имя для файла `name`.
+    pub fn apply(&self, name: &str) -> String {
+        self.0.replace(Self::PLACEHOLDER, name)
+    }
+}
+
+impl Default for TempNamePattern {
+    fn default() -> Self {
+        Self(".{name}.uploading".to_string())
+    }
+}
+
+impl TryFrom<String> for TempNamePattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Self::new(pattern).map_err(|_| {
+            "шаблон временного имени должен содержать {name} и давать допустимое имя файла"
+                .to_string()
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use std::path::Path;
+
+use crate::{
+    error::{FsError, FsResult},
+    types::FtpConnectParams,
+};
+
+/// Учётные данные одной записи `.netrc`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetrcEntry {
+    pub login: Option<String>,
+    pub password: Option<String>,
+    pub account: Option<String>,
+}
+
+/// Разобранный файл `.netrc`: записи `machine` и необязательная `default`.
+///
+/// Макросы `macdef` пропускаются. Поддерживаются значения в двойных
+/// кавычках (расширение GNU inetutils) и комментарии `#`.
+#[derive(Debug, Clone, Default)]
+pub struct Netrc {
+    machines: Vec<(String, NetrcEntry)>,
+    default: Option<NetrcEntry>,
+}
+
+impl Netrc {
+    /// Прочитать и разобрать файл.
+    pub fn from_file(path: &Path) -> FsResult<Self> {
+        let text = std::fs::read_to_string(path).map_err(FsError::Io)?;
+        Self::parse(&text)
+    }
+
+    /// Разобрать содержимое `.netrc`.
+    pub fn parse(text: &str) -> FsResult<Self> {
+        let mut netrc = Self::default();
+        // Куда пишутся login/password/account: `None` — вне записи
+        let mut current: Option<(Option<String>, NetrcEntry)> = None;
+        let mut in_macdef = false;
+
+        for (line_no, line) in text.lines().enumerate() {
+            // Тело macdef длится до первой пустой строки
+            if in_macdef {
+                in_macdef = !line.trim().is_empty();
+                continue;
+            }
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            let tokens = tokenize(line).map_err(|e| netrc_error(line_no, &e))?;
+            let mut tokens = tokens.into_iter();
+            while let Some(token) = tokens.next() {
+                let mut value = || {
+                    tokens
+                        .next()
+                        .ok_or_else(|| netrc_error(line_no, &format!("missing value for {token}")))
+                };
+                match token.as_str() {
+                    "machine" => {
+                        let host = value()?;
+                        netrc.finish(current.take());
+                        current = Some((Some(host), NetrcEntry::default()));
+                    }
+                    "default" => {
+                        netrc.finish(current.take());
+                        current = Some((None, NetrcEntry::default()));
+                    }
+                    "login" | "password" | "account" => {
+                        let v = value()?;
+                        let (_, entry) = current.as_mut().ok_or_else(|| {
+                            netrc_error(line_no, &format!("{token} outside of machine/default"))
+                        })?;
+                        match token.as_str() {
+                            "login" => entry.login = Some(v),
+                            "password" => entry.password = Some(v),
+                            _ => entry.account = Some(v),
+                        }
+                    }
+                    "macdef" => {
+                        value()?;
+                        in_macdef = true;
+                        break;
+                    }
+                    other => return Err(netrc_error(line_no, &format!("unknown token {other:?}"))),
+                }
+            }
+        }
+        netrc.finish(current);
+        Ok(netrc)
+    }
+
+    /// Запись `machine` для `host` (без учёта регистра).
+    ///
+    /// Запись `default` здесь не учитывается: она подходит любому хосту,
+    /// и отдавать её надо явно — через [`Netrc::default_entry`].
+    pub fn lookup(&self, host: &str) -> Option<&NetrcEntry> {
+        self.machines
+            .iter()
+            .find(|(machine, _)| machine.eq_ignore_ascii_case(host))
+            .map(|(_, entry)| entry)
+    }
+
+    /// Запись `default`, если она есть.
+    pub fn default_entry(&self) -> Option<&NetrcEntry> {
+        self.default.as_ref()
+    }
+
+    fn finish(&mut self, entry: Option<(Option<String>, NetrcEntry)>) {
+        match entry {
+            Some((Some(host), entry)) => self.machines.push((host, entry)),
+            Some((None, entry)) => self.default = Some(entry),
+            None => {}
+        }
+    }
+}
+
+impl FtpConnectParams {
+    /// Дополнить пустые `username` и `password` из записи `machine` для
+    /// `host` в `.netrc`. Возвращает `true`, если что-то подставлено.
+    pub fn fill_from_netrc(&mut self, netrc: &Netrc) -> bool {
+        netrc
+            .lookup(&self.host)
+            .is_some_and(|entry| self.fill_from_netrc_entry(entry))
+    }
+
+    /// Дополнить пустые `username` и `password` из записи `.netrc`.
+    ///
+    /// Пароль берётся, только если логин записи совпадает с уже указанным
+    /// (или в записи логина нет). Возвращает `true`, если что-то подставлено.
+    pub fn fill_from_netrc_entry(&mut self, entry: &NetrcEntry) -> bool {
+        let mut filled = false;
+
+        if self.username.is_empty()
+            && let Some(login) = &entry.login
+        {
+            self.username = login.clone();
+            filled = true;
+        }
+
+        let login_matches = entry.login.as_ref().is_none_or(|l| *l == self.username);
+        if self.password.is_empty()
+            && login_matches
+            && let Some(password) = &entry.password
+        {
+            self.password = password.clone();
+            filled = true;
+        }
+
+        filled
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => token.extend(chars.next()),
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated quote".to_string()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+fn netrc_error(line_no: usize, message: &str) -> FsError {
+    FsError::Netrc(format!("line {}: {}", line_no + 1, message))
+}
@@ -0,0 +1,105 @@
+//! Ограничение скорости передачи.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// Как часто ждущая передача перечитывает лимит: после его смены
+/// новая скорость действует не позже чем через этот интервал.
+const RECHECK: Duration = Duration::from_millis(100);
+
+/// Ограничитель скорости в байтах в секунду («ведро токенов»).
+///
+/// Клоны разделяют состояние: один ограничитель делит полосу между всеми
+/// передачами, которые его используют. Лимит можно менять на ходу
+/// ([`RateLimiter::set_limit`]); `None` — без ограничения.
+#[derive(Debug, Clone)]
+pub struct RateLimiter(Arc<Mutex<Bucket>>);
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Option<u64>,
+    /// Сколько байт можно передать без ожидания; отрицательное — долг,
+    /// который передача отрабатывает паузой.
+    available: f64,
+    /// По часам tokio, чтобы в тестах паузы шли по остановленному времени.
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Пополнить запас за прошедшее время — не больше, чем на секунду
+    /// передачи вперёд.
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+        match self.limit {
+            Some(limit) => {
+                self.available = (self.available + elapsed * limit as f64).min(limit as f64);
+            }
+            None => self.available = 0.0,
+        }
+    }
+
+    /// Сколько ждать, пока долг не будет отработан.
+    fn delay(&self) -> Duration {
+        match self.limit {
+            Some(limit) if self.available < 0.0 => {
+                Duration::from_secs_f64(-self.available / limit.max(1) as f64)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self(Arc::new(Mutex::new(Bucket {
+            limit: limit.filter(|&l| l > 0),
+            available: 0.0,
+            updated: Instant::now(),
+        })))
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None)
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.0.lock().unwrap().limit
+    }
+
+    /// Сменить лимит; 0 и `None` снимают ограничение.
+    pub fn set_limit(&self, limit: Option<u64>) {
+        let mut bucket = self.0.lock().unwrap();
+        bucket.refill();
+        bucket.limit = limit.filter(|&l| l > 0);
+    }
+
+    /// Учесть `bytes` переданных байт и подождать, если скорость превышена.
+    pub async fn acquire(&self, bytes: u64) {
+        let mut delay = {
+            let mut bucket = self.0.lock().unwrap();
+            bucket.refill();
+            if bucket.limit.is_some() {
+                bucket.available -= bytes as f64;
+            }
+            bucket.delay()
+        };
+        while !delay.is_zero() {
+            tokio::time::sleep(delay.min(RECHECK)).await;
+            let mut bucket = self.0.lock().unwrap();
+            bucket.refill();
+            delay = bucket.delay();
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
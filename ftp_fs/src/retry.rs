@@ -0,0 +1,78 @@
+//! Повтор передачи файла после временных ошибок.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::FsError;
+
+/// Класс ошибок, после которых передачу файла имеет смысл повторить.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RetryClass {
+    /// 425, 426 или обрыв канала данных.
+    DataConnection,
+    /// 450 — файл занят или временно недоступен.
+    FileBusy,
+    /// 421 или обрыв управляющего соединения: перед повтором нужно
+    /// переподключиться.
+    ConnectionLost,
+}
+
+/// Когда и как часто повторять передачу файла.
+///
+/// Пауза перед второй попыткой — `initial_backoff`, перед каждой следующей
+/// вдвое дольше, но не больше `max_backoff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Сколько всего попыток на файл, включая первую; 1 — без повторов.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Какие ошибки повторять; остальные сразу завершают файл.
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl RetryPolicy {
+    /// Одна попытка, без повторов.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Повторять ли файл, если попытка `attempt` (с единицы) завершилась `error`.
+    pub fn should_retry(&self, error: &FsError, attempt: u32) -> bool {
+        attempt < self.max_attempts
+            && error
+                .retry_class()
+                .is_some_and(|class| self.retry_on.contains(&class))
+    }
+
+    /// Пауза после неудачной попытки `attempt` (с единицы).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            retry_on: vec![
+                RetryClass::DataConnection,
+                RetryClass::FileBusy,
+                RetryClass::ConnectionLost,
+            ],
+        }
+    }
+}
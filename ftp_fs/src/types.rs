@@ -1,7 +1,19 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    conflict::ConflictPolicy, error::FsError, names::TempNamePattern, rate::RateLimiter,
+    retry::RetryPolicy,
+};
+
 /// Тип записи файловой системы.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum FileKind {
     File,
@@ -11,6 +23,7 @@ pub enum FileKind {
 
 /// Единое представление файла или директории для обеих файловых систем.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct FileEntry {
     /// Имя файла или директории (без пути).
     pub name: String,
@@ -34,22 +47,25 @@ impl FileEntry {
     pub fn size_display(&self) -> String {
         match self.size {
             None => "—".to_string(),
-            Some(s) => {
-                let s = s as f64;
-                if s < 1024.0 {
-                    format!("{:.0} B", s)
-                } else if s < 1024.0 * 1024.0 {
-                    format!("{:.1} KB", s / 1024.0)
-                } else if s < 1024.0 * 1024.0 * 1024.0 {
-                    format!("{:.1} MB", s / 1024.0 / 1024.0)
-                } else {
-                    format!("{:.1} GB", s / 1024.0 / 1024.0 / 1024.0)
-                }
-            }
+            Some(s) => format_size(s),
         }
     }
 }
 
+/// Человекочитаемый размер в байтах: `"512 B"`, `"1.5 MB"`.
+pub fn format_size(bytes: u64) -> String {
+    let s = bytes as f64;
+    if s < 1024.0 {
+        format!("{:.0} B", s)
+    } else if s < 1024.0 * 1024.0 {
+        format!("{:.1} KB", s / 1024.0)
+    } else if s < 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} MB", s / 1024.0 / 1024.0)
+    } else {
+        format!("{:.1} GB", s / 1024.0 / 1024.0 / 1024.0)
+    }
+}
+
 /// Сортирует список элементов файловой системы:
 /// директории первыми, затем файлы. Внутри групп — по алфавиту.
 pub fn sort_file_entries(files: &mut [FileEntry]) {
@@ -84,13 +100,293 @@ impl FtpConnectParams {
     }
 }
 
-/// Прогресс передачи файла — для обратных вызовов и SSE-событий.
-#[derive(Debug, Clone)]
-pub struct TransferProgress {
-    /// Имя передаваемого файла.
-    pub filename: String,
-    /// Количество уже переданных байт.
-    pub bytes_transferred: u64,
-    /// Общий размер файла, если известен.
-    pub total_bytes: Option<u64>,
+/// Режим канала данных FTP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpMode {
+    /// PASV — клиент подключается к порту, открытому сервером.
+    #[default]
+    Passive,
+    /// EPSV (RFC 2428) — нужен некоторым серверам, в том числе по IPv6.
+    ExtendedPassive,
+    /// PORT — сервер подключается к клиенту.
+    Active,
+}
+
+/// Почему файл из запрошенного списка не передавался.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Директории не передаются.
+    Directory,
+    /// Не обычный файл: устройство, сокет, FIFO.
+    NotRegularFile,
+    /// Пакет прерван: соединение с сервером потеряно на предыдущем файле.
+    Aborted,
+    /// Файл отменён через [`CancelFlags`].
+    Cancelled,
+    /// Файл уже есть в месте назначения ([`ConflictPolicy::Skip`]).
+    Exists,
+    /// В месте назначения уже не старее или того же размера, что источник.
+    UpToDate,
+}
+
+/// Параметры передачи пачки файлов.
+#[derive(Debug, Clone, Default)]
+pub struct TransferOptions {
+    /// Повтор файла после временных ошибок.
+    pub retry: RetryPolicy,
+    /// Ограничители скорости: передача ждёт каждый из них после каждого
+    /// блока данных, так что действует самый строгий.
+    pub limiters: Vec<RateLimiter>,
+    /// Что делать с файлами, которые уже есть в месте назначения.
+    pub conflict: ConflictPolicy,
+    /// Решения для отдельных файлов по имени — вместо `conflict`.
+    pub conflicts: HashMap<String, ConflictPolicy>,
+    /// Загружать под временным именем по шаблону и переименовывать на
+    /// место (RNFR/RNTO) после проверки; `None` — сразу под настоящим.
+    pub atomic_upload: Option<TempNamePattern>,
+}
+
+impl TransferOptions {
+    /// Политика конфликта для файла `name`.
+    pub fn conflict_for(&self, name: &str) -> ConflictPolicy {
+        self.conflicts.get(name).copied().unwrap_or(self.conflict)
+    }
+
+    /// Нужно ли искать файлы в месте назначения: при перезаписи без
+    /// условий их наличие ничего не меняет.
+    pub(crate) fn checks_conflicts(&self) -> bool {
+        std::iter::once(&self.conflict)
+            .chain(self.conflicts.values())
+            .any(|&policy| policy != ConflictPolicy::Overwrite)
+    }
+}
+
+/// Событие передачи пачки файлов; `index` — позиция файла в запросе.
+///
+/// Порядок гарантирован: события файла идут после всех событий файлов с
+/// меньшим индексом. Для каждого запрошенного файла приходит ровно одно
+/// завершающее событие — `Completed`, `Skipped` или `Failed`; перед ним
+/// могут быть `Renamed`, `Started`, `Progress` с растущим числом байт и
+/// `Verified`.
+/// После `Retrying` число байт в `Progress` может начаться заново — с
+/// места, откуда удалось продолжить передачу.
+#[derive(Debug)]
+pub enum TransferEvent {
+    /// Файл с запрошенным именем уже есть в месте назначения: передача
+    /// сохранит файл как `target`.
+    Renamed { index: usize, target: String },
+    Started {
+        index: usize,
+        name: String,
+        /// Размер файла, если известен.
+        total: Option<u64>,
+    },
+    /// Передан очередной блок данных.
+    Progress {
+        index: usize,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// Попытка не удалась; следующая, с номером `attempt`, начнётся
+    /// через `delay`.
+    Retrying {
+        index: usize,
+        attempt: u32,
+        delay: Duration,
+        error: FsError,
+    },
+    /// Размер файла на сервере совпал с переданным (команда SIZE).
+    Verified { index: usize, size: u64 },
+    Completed {
+        index: usize,
+        name: String,
+        bytes: u64,
+        duration: Duration,
+    },
+    Skipped {
+        index: usize,
+        name: String,
+        reason: SkipReason,
+    },
+    Failed {
+        index: usize,
+        name: String,
+        error: FsError,
+    },
+}
+
+impl TransferEvent {
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Renamed { index, .. }
+            | Self::Started { index, .. }
+            | Self::Progress { index, .. }
+            | Self::Retrying { index, .. }
+            | Self::Verified { index, .. }
+            | Self::Completed { index, .. }
+            | Self::Skipped { index, .. }
+            | Self::Failed { index, .. } => *index,
+        }
+    }
+}
+
+/// Отмена отдельных файлов пачки по их индексу.
+///
+/// Клоны разделяют состояние: передача проверяет флаг перед файлом и после
+/// каждого блока данных.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlags(Arc<Mutex<HashSet<usize>>>);
+
+impl CancelFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self, index: usize) {
+        self.0.lock().unwrap().insert(index);
+    }
+
+    pub fn is_cancelled(&self, index: usize) -> bool {
+        self.0.lock().unwrap().contains(&index)
+    }
+}
+
+/// Итог передачи одного файла.
+#[derive(Debug)]
+pub enum TransferOutcome {
+    Transferred,
+    Skipped(SkipReason),
+    Failed(FsError),
+}
+
+/// Результат по одному запрошенному файлу.
+#[derive(Debug)]
+pub struct TransferItem {
+    pub name: String,
+    pub outcome: TransferOutcome,
+    /// Сколько байт передано; для ошибок — 0.
+    pub bytes: u64,
+    pub duration: Duration,
+    /// Размер проверен: командой SIZE или, при скачивании, по листингу.
+    pub verified: bool,
+    /// Сколько попыток понадобилось; 0 — передача не начиналась.
+    pub attempts: u32,
+    /// Под каким именем файл сохранён, если не под запрошенным.
+    pub renamed: Option<String>,
+}
+
+/// Отчёт о передаче пачки файлов: по записи на каждый запрошенный файл,
+/// в порядке запроса. Собирается из [`TransferEvent`] методом
+/// [`TransferReport::record`].
+#[derive(Debug, Default)]
+pub struct TransferReport {
+    pub items: Vec<TransferItem>,
+    /// Сведения о текущем файле до его завершающего события.
+    pending: Option<Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    index: usize,
+    verified: bool,
+    attempts: u32,
+    renamed: Option<String>,
+}
+
+impl TransferReport {
+    /// Учесть событие: завершающие события добавляют запись в отчёт.
+    pub fn record(&mut self, event: TransferEvent) {
+        let current = event.index();
+        let (name, outcome, bytes, duration) = match event {
+            TransferEvent::Renamed { target, .. } => {
+                self.pending(current).renamed = Some(target);
+                return;
+            }
+            TransferEvent::Started { .. } => {
+                self.pending(current).attempts = 1;
+                return;
+            }
+            TransferEvent::Retrying { attempt, .. } => {
+                self.pending(current).attempts = attempt;
+                return;
+            }
+            TransferEvent::Verified { .. } => {
+                self.pending(current).verified = true;
+                return;
+            }
+            TransferEvent::Progress { .. } => return,
+            TransferEvent::Completed {
+                name,
+                bytes,
+                duration,
+                ..
+            } => (name, TransferOutcome::Transferred, bytes, duration),
+            TransferEvent::Skipped { name, reason, .. } => {
+                (name, TransferOutcome::Skipped(reason), 0, Duration::ZERO)
+            }
+            TransferEvent::Failed { name, error, .. } => {
+                (name, TransferOutcome::Failed(error), 0, Duration::ZERO)
+            }
+        };
+        let pending = self
+            .pending
+            .take()
+            .filter(|p| p.index == current)
+            .unwrap_or_default();
+        self.items.push(TransferItem {
+            name,
+            outcome,
+            bytes,
+            duration,
+            verified: pending.verified,
+            attempts: pending.attempts,
+            renamed: pending.renamed,
+        });
+    }
+
+    /// Сведения о файле `index`; события прошлого файла отбрасываются.
+    fn pending(&mut self, index: usize) -> &mut Pending {
+        if self.pending.as_ref().is_none_or(|p| p.index != index) {
+            self.pending = Some(Pending {
+                index,
+                ..Pending::default()
+            });
+        }
+        self.pending.as_mut().unwrap()
+    }
+
+    pub fn transferred(&self) -> usize {
+        self.count(|o| matches!(o, TransferOutcome::Transferred))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|o| matches!(o, TransferOutcome::Skipped(_)))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, TransferOutcome::Failed(_)))
+    }
+
+    /// Всего передано байт.
+    pub fn bytes(&self) -> u64 {
+        self.items.iter().map(|item| item.bytes).sum()
+    }
+
+    /// Один из файлов оборвал управляющее соединение: остальные
+    /// передавать бессмысленно.
+    pub fn connection_lost(&self) -> bool {
+        self.items.iter().any(
+            |item| matches!(&item.outcome, TransferOutcome::Failed(e) if e.is_connection_lost()),
+        )
+    }
+
+    /// Ни один файл не завершился ошибкой.
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+
+    fn count(&self, pred: impl Fn(&TransferOutcome) -> bool) -> usize {
+        self.items.iter().filter(|item| pred(&item.outcome)).count()
+    }
 }
@@ -0,0 +1,56 @@
+use std::time::{Duration, SystemTime};
+
+use ftp_fs::{ConflictPolicy, FileStat, Resolution, SkipReason, free_name};
+
+fn stat(size: u64, secs: u64) -> FileStat {
+    FileStat {
+        size: Some(size),
+        modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+    }
+}
+
+#[test]
+fn conditional_policies_compare_source_and_target() {
+    let newer = stat(10, 2000);
+    let older = stat(10, 1000);
+
+    let policy = ConflictPolicy::OverwriteIfNewer;
+    assert_eq!(policy.resolve(&newer, &older), Resolution::Transfer);
+    assert_eq!(
+        policy.resolve(&older, &newer),
+        Resolution::Skip(SkipReason::UpToDate)
+    );
+    assert_eq!(
+        policy.resolve(&newer, &FileStat::default()),
+        Resolution::Transfer,
+        "без времени изменения — перезаписать"
+    );
+
+    let policy = ConflictPolicy::OverwriteIfSizeDiffers;
+    assert_eq!(
+        policy.resolve(&newer, &older),
+        Resolution::Skip(SkipReason::UpToDate)
+    );
+    assert_eq!(policy.resolve(&stat(11, 0), &older), Resolution::Transfer);
+}
+
+#[test]
+fn fractional_seconds_do_not_make_a_file_newer() {
+    let source = FileStat {
+        size: Some(1),
+        modified: Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1_000_500)),
+    };
+    assert_eq!(
+        ConflictPolicy::OverwriteIfNewer.resolve(&source, &stat(1, 1000)),
+        Resolution::Skip(SkipReason::UpToDate)
+    );
+}
+
+#[test]
+fn free_name_keeps_extension_and_skips_taken() {
+    let taken = ["report (1).pdf".to_string()];
+    let is_taken = |name: &str| taken.iter().any(|t| t == name);
+    assert_eq!(free_name("report.pdf", is_taken), "report (2).pdf");
+    assert_eq!(free_name("README", is_taken), "README (1)");
+    assert_eq!(free_name(".profile", is_taken), ".profile (1)");
+}
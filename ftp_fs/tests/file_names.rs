@@ -0,0 +1,60 @@
+use ftp_fs::{FsError, TempNamePattern, validate_file_name, validate_file_names};
+
+#[test]
+fn accepts_plain_names() {
+    for name in ["hello.txt", "archive.tar.gz", ".hidden", "..dots", "файл.md", "a b"] {
+        assert_eq!(validate_file_name(name).unwrap(), name);
+    }
+}
+
+#[test]
+fn rejects_hostile_names() {
+    let hostile = [
+        "",
+        ".",
+        "..",
+        "../../etc/cron.d/x",
+        "/etc/passwd",
+        "dir/file.txt",
+        "..\\..\\windows\\system32",
+        "C:\\boot.ini",
+        "file\0.txt",
+        "file.txt\r\nDELE important",
+    ];
+    for name in hostile {
+        assert!(
+            matches!(validate_file_name(name), Err(FsError::InvalidFileName { .. })),
+            "name {name:?} must be refused"
+        );
+    }
+}
+
+#[test]
+fn one_hostile_name_rejects_the_whole_batch() {
+    let names = vec!["ok.txt".to_string(), "../escape".to_string()];
+    assert!(matches!(
+        validate_file_names(&names),
+        Err(FsError::InvalidFileName { name, .. }) if name == "../escape"
+    ));
+}
+
+#[test]
+fn temp_name_pattern_must_wrap_the_name() {
+    assert_eq!(
+        TempNamePattern::default().apply("report.pdf"),
+        ".report.pdf.uploading"
+    );
+    assert_eq!(
+        TempNamePattern::new("{name}.part").unwrap().apply("a.txt"),
+        "a.txt.part"
+    );
+    for pattern in ["upload.tmp", "{name}", "tmp/{name}", ""] {
+        assert!(
+            matches!(
+                TempNamePattern::new(pattern),
+                Err(FsError::InvalidFileName { .. })
+            ),
+            "{pattern:?}"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use ftp_fs::{FileSystem, FsError, LocalFs};
+
+/// Временная директория теста; удаляется при завершении.
+struct TempRoot(PathBuf);
+
+impl TempRoot {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ftp_fs_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn local_fs_stays_inside_root() {
+    let tmp = TempRoot::new("root");
+    let root = tmp.0.join("root");
+    std::fs::create_dir_all(root.join("inner")).unwrap();
+
+    let mut fs = LocalFs::new(root.clone()).unwrap();
+    fs.change_dir("inner").await.unwrap();
+    assert_eq!(fs.current_dir().await.unwrap(), "/inner");
+
+    assert!(fs.resolve("../../..").is_err());
+    fs.change_dir("..").await.unwrap();
+    fs.change_dir("..").await.unwrap();
+    assert_eq!(fs.current_dir().await.unwrap(), "/");
+
+    fs.change_dir("/inner").await.unwrap();
+    assert_eq!(fs.path(), root.canonicalize().unwrap().join("inner"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks_cannot_escape_root() {
+    use std::os::unix::fs::symlink;
+
+    let tmp = TempRoot::new("symlinks");
+    let root = tmp.0.join("root");
+    let outside = tmp.0.join("outside");
+    std::fs::create_dir_all(root.join("inner")).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+
+    symlink("/", root.join("absolute")).unwrap();
+    symlink("../outside", root.join("relative")).unwrap();
+    symlink("../../outside/secret.txt", root.join("inner/secret.txt")).unwrap();
+    symlink("inner", root.join("alias")).unwrap();
+
+    let mut fs = LocalFs::new(root.clone()).unwrap();
+    for escape in ["/absolute", "relative", "relative/..", "inner/secret.txt"] {
+        assert!(
+            matches!(fs.resolve(escape), Err(FsError::PermissionDenied { .. })),
+            "{escape:?} must stay inside the root"
+        );
+    }
+    assert!(matches!(
+        fs.change_dir("relative").await,
+        Err(FsError::PermissionDenied { .. })
+    ));
+    assert_eq!(fs.current_dir().await.unwrap(), "/");
+
+    // Ссылка, не выходящая за корень, работает как обычная директория
+    fs.change_dir("alias").await.unwrap();
+    assert_eq!(fs.current_dir().await.unwrap(), "/inner");
+}
@@ -0,0 +1,113 @@
+use ftp_fs::{FsError, FtpConnectParams, Netrc, NetrcEntry};
+
+fn entry(login: Option<&str>, password: Option<&str>) -> NetrcEntry {
+    NetrcEntry {
+        login: login.map(str::to_string),
+        password: password.map(str::to_string),
+        account: None,
+    }
+}
+
+#[test]
+fn machines_and_default_are_separate() {
+    let netrc = Netrc::parse(
+        "machine ftp.example.com login alice password secret\n\
+         machine other.example.com\n  login bob\n  password hunter2 account acct\n\
+         default login anonymous password guest@\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        netrc.lookup("FTP.Example.com"),
+        Some(&entry(Some("alice"), Some("secret")))
+    );
+    let other = netrc.lookup("other.example.com").unwrap();
+    assert_eq!(other.login.as_deref(), Some("bob"));
+    assert_eq!(other.account.as_deref(), Some("acct"));
+
+    // default не подходит к чужим хостам сам по себе
+    assert_eq!(netrc.lookup("unknown.example.com"), None);
+    assert_eq!(
+        netrc.default_entry(),
+        Some(&entry(Some("anonymous"), Some("guest@")))
+    );
+}
+
+#[test]
+fn macdef_body_and_comments_are_skipped() {
+    let netrc = Netrc::parse(
+        "# комментарий\n\
+         macdef init\n\
+         cd /pub\n\
+         machine evil.example.com login x password y\n\
+         \n\
+         machine ftp.example.com login alice password secret\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        netrc.lookup("evil.example.com"),
+        None,
+        "строка из тела macdef"
+    );
+    assert!(netrc.lookup("ftp.example.com").is_some());
+}
+
+#[test]
+fn quoted_values_keep_spaces_and_escapes() {
+    let netrc =
+        Netrc::parse(r#"machine ftp.example.com login "john doe" password "pa ss\"word""#).unwrap();
+    assert_eq!(
+        netrc.lookup("ftp.example.com"),
+        Some(&entry(Some("john doe"), Some("pa ss\"word")))
+    );
+
+    assert!(matches!(
+        Netrc::parse("machine ftp.example.com password \"open"),
+        Err(FsError::Netrc(_))
+    ));
+}
+
+#[test]
+fn malformed_files_are_rejected() {
+    for text in [
+        "machine",
+        "machine ftp.example.com login",
+        "login alice",
+        "machine ftp.example.com port 21",
+    ] {
+        assert!(
+            matches!(Netrc::parse(text), Err(FsError::Netrc(_))),
+            "{text:?}"
+        );
+    }
+}
+
+#[test]
+fn missing_password_fills_only_login() {
+    let netrc = Netrc::parse("machine ftp.example.com login alice").unwrap();
+
+    let mut params = FtpConnectParams::new("ftp.example.com", 21, "", "");
+    assert!(params.fill_from_netrc(&netrc));
+    assert_eq!(params.username, "alice");
+    assert_eq!(params.password, "");
+}
+
+#[test]
+fn password_needs_matching_login() {
+    let netrc = Netrc::parse("machine ftp.example.com login alice password secret").unwrap();
+
+    let mut params = FtpConnectParams::new("ftp.example.com", 21, "bob", "");
+    assert!(!params.fill_from_netrc(&netrc));
+    assert_eq!(params.password, "");
+
+    let mut params = FtpConnectParams::new("ftp.example.com", 21, "alice", "typed");
+    assert!(
+        !params.fill_from_netrc(&netrc),
+        "введённый пароль не заменяется"
+    );
+    assert_eq!(params.password, "typed");
+
+    let mut params = FtpConnectParams::new("other.example.com", 21, "", "");
+    assert!(!params.fill_from_netrc(&netrc));
+}
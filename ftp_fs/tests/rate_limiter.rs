@@ -0,0 +1,132 @@
+//! Ограничитель скорости: паузы считаются по остановленным часам tokio,
+//! поэтому тесты идут мгновенно и не зависят от загрузки машины.
+
+use std::time::Duration;
+
+use ftp_fs::RateLimiter;
+use tokio::time::Instant;
+
+/// Сколько заняли `bytes` байт, переданные порциями по `chunk` через все
+/// ограничители по очереди — так же, как их учитывает передача.
+async fn transfer(limiters: &[RateLimiter], bytes: u64, chunk: u64) -> Duration {
+    let start = Instant::now();
+    let mut sent = 0;
+    while sent < bytes {
+        let n = chunk.min(bytes - sent);
+        for limiter in limiters {
+            limiter.acquire(n).await;
+        }
+        sent += n;
+    }
+    start.elapsed()
+}
+
+/// `elapsed` в пределах `expected` с запасом на последнюю проверку лимита.
+fn assert_about(elapsed: Duration, expected: Duration) {
+    assert!(
+        elapsed >= expected && elapsed <= expected + Duration::from_millis(110),
+        "ожидалось около {expected:?}, прошло {elapsed:?}"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn excess_is_paid_off_with_a_pause() {
+    let limiter = RateLimiter::new(Some(1000));
+
+    assert_about(
+        transfer(&[limiter], 3000, 100).await,
+        Duration::from_secs(3),
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn zero_and_none_mean_unlimited() {
+    for limiter in [RateLimiter::new(None), RateLimiter::new(Some(0))] {
+        assert_eq!(limiter.limit(), None);
+        assert_eq!(transfer(&[limiter], 1 << 30, 1 << 20).await, Duration::ZERO);
+    }
+
+    let limiter = RateLimiter::new(Some(1000));
+    limiter.set_limit(Some(0));
+    assert_eq!(limiter.limit(), None);
+    assert_eq!(transfer(&[limiter], 1 << 30, 1 << 20).await, Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_time_saves_at_most_one_second() {
+    let limiter = RateLimiter::new(Some(1000));
+    tokio::time::advance(Duration::from_secs(60)).await;
+
+    // Запас за минуту простоя — только секунда передачи
+    assert_eq!(
+        transfer(std::slice::from_ref(&limiter), 1000, 100).await,
+        Duration::ZERO
+    );
+    assert_about(
+        transfer(&[limiter], 2000, 100).await,
+        Duration::from_secs(2),
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn new_limit_applies_to_a_waiting_transfer() {
+    let limiter = RateLimiter::new(Some(100));
+    let start = Instant::now();
+    // При 100 Б/с долг в 1000 байт отрабатывался бы 10 секунд
+    let waiting = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.acquire(1000).await }
+    });
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    limiter.set_limit(Some(9000));
+    waiting.await.unwrap();
+    // Оставшиеся 900 байт при 9000 Б/с — ещё 0,1 с
+    assert_about(start.elapsed(), Duration::from_millis(1100));
+
+    let waiting = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.acquire(1_000_000).await }
+    });
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    limiter.set_limit(None);
+    let start = Instant::now();
+    waiting.await.unwrap();
+    assert!(start.elapsed() <= Duration::from_millis(100));
+}
+
+#[tokio::test(start_paused = true)]
+async fn clones_share_the_bandwidth() {
+    let limiter = RateLimiter::new(Some(1000));
+    let other = limiter.clone();
+
+    let start = Instant::now();
+    tokio::join!(
+        transfer(std::slice::from_ref(&limiter), 1000, 100),
+        transfer(std::slice::from_ref(&other), 1000, 100)
+    );
+    // Две передачи по 1000 байт делят 1000 Б/с
+    assert_about(start.elapsed(), Duration::from_secs(2));
+
+    other.set_limit(Some(5000));
+    assert_eq!(limiter.limit(), Some(5000));
+}
+
+#[tokio::test(start_paused = true)]
+async fn strictest_of_global_and_own_limit_wins() {
+    // Свой лимит строже общего
+    let global = RateLimiter::new(Some(1000));
+    let own = RateLimiter::new(Some(500));
+    assert_about(
+        transfer(&[global, own], 1000, 100).await,
+        Duration::from_secs(2),
+    );
+
+    // Общий строже своего и делится между передачами
+    let global = RateLimiter::new(Some(1000));
+    let first = [global.clone(), RateLimiter::new(Some(5000))];
+    let second = [global, RateLimiter::new(Some(5000))];
+    let start = Instant::now();
+    tokio::join!(transfer(&first, 1000, 100), transfer(&second, 1000, 100));
+    assert_about(start.elapsed(), Duration::from_secs(2));
+}
@@ -0,0 +1,134 @@
+use ftp_fs::FsError;
+use suppaftp::{FtpError, Status, types::Response};
+
+fn reply(code: u32, text: &str) -> FsError {
+    let body = format!("{} {}\r\n", code, text).into_bytes();
+    FsError::from_ftp(
+        FtpError::UnexpectedResponse(Response::new(Status::from(code), body)),
+        "report.pdf",
+    )
+}
+
+#[test]
+fn classifies_file_errors_by_reply_code() {
+    assert!(matches!(
+        reply(550, "report.pdf: No such file or directory"),
+        FsError::PathNotFound { path, .. } if path == "report.pdf"
+    ));
+    assert!(matches!(
+        reply(550, "report.pdf: Permission denied"),
+        FsError::PermissionDenied { path, .. } if path == "report.pdf"
+    ));
+    assert!(matches!(
+        reply(553, "File name not allowed"),
+        FsError::InvalidFileName { .. }
+    ));
+    assert!(matches!(
+        reply(552, "Quota exceeded"),
+        FsError::QuotaExceeded { .. }
+    ));
+    assert!(matches!(
+        reply(450, "File in use"),
+        FsError::FileBusy { .. }
+    ));
+}
+
+#[test]
+fn multi_line_reply_keeps_its_lines_without_codes() {
+    let body = b"530-Login incorrect.\r\n Only 2024 accounts\r\n530 \r\n".to_vec();
+    let err = FsError::from_ftp(
+        FtpError::UnexpectedResponse(Response::new(Status::from(530), body)),
+        "report.pdf",
+    );
+    assert!(
+        matches!(
+            &err,
+            FsError::NotLoggedIn { message, .. }
+                if message == "Login incorrect.\n Only 2024 accounts"
+        ),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn classifies_session_errors_by_reply_code() {
+    assert!(matches!(
+        reply(530, "Login incorrect."),
+        FsError::NotLoggedIn { message, .. } if message == "Login incorrect."
+    ));
+    assert!(matches!(
+        reply(425, "Can't open data connection."),
+        FsError::DataConnection { code: 425, .. }
+    ));
+    assert!(matches!(
+        reply(502, "Command not implemented."),
+        FsError::NotSupported { .. }
+    ));
+
+    let closing = reply(421, "Timeout.");
+    assert!(closing.is_connection_lost());
+    assert_eq!(closing.reply_code(), Some(421));
+}
+
+#[test]
+fn mapped_errors_keep_the_reply_code() {
+    for (code, text) in [
+        (421, "Timeout."),
+        (425, "Can't open data connection."),
+        (426, "Connection closed; transfer aborted."),
+        (450, "File in use"),
+        (452, "Insufficient storage space"),
+        (500, "Syntax error"),
+        (502, "Command not implemented."),
+        (504, "Command not implemented for that parameter"),
+        (530, "Login incorrect."),
+        (532, "Need account for storing files"),
+        (550, "report.pdf: No such file or directory"),
+        (550, "report.pdf: Permission denied"),
+        (552, "Quota exceeded"),
+        (553, "File name not allowed"),
+    ] {
+        let err = reply(code, text);
+        assert!(!matches!(err, FsError::Ftp(_)), "{code} не разобран");
+        assert_eq!(err.reply_code(), Some(code), "{err:?}");
+    }
+}
+
+#[test]
+fn local_errors_have_no_reply_code() {
+    let missing = FsError::PathNotFound {
+        path: "report.pdf".into(),
+        code: None,
+    };
+    assert_eq!(missing.reply_code(), None);
+    assert_eq!(FsError::ConnectionLost.reply_code(), None);
+}
+
+#[test]
+fn connection_drop_means_reconnect() {
+    let eof = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+    let lost = FsError::from_ftp(FtpError::ConnectionError(eof), "");
+    assert!(lost.is_connection_lost());
+    let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+    assert!(FsError::from_ftp(FtpError::ConnectionError(reset), "").is_connection_lost());
+}
+
+#[test]
+fn unparsable_reply_is_a_protocol_error() {
+    // Так suppaftp сообщает о ненумерованном ответе на SIZE или MDTM
+    let err = FsError::from_ftp(FtpError::BadResponse, "report.pdf");
+    assert!(
+        matches!(err, FsError::Ftp(FtpError::BadResponse)),
+        "{err:?}"
+    );
+    assert!(!err.is_connection_lost());
+    assert_eq!(err.retry_class(), None);
+}
+
+#[test]
+fn unknown_codes_stay_generic() {
+    let err = reply(551, "Page type unknown");
+    assert!(matches!(err, FsError::Ftp(_)));
+    assert_eq!(err.reply_code(), Some(551));
+    assert!(!err.is_connection_lost());
+}
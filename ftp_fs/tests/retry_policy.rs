@@ -0,0 +1,60 @@
+use std::{io, time::Duration};
+
+use ftp_fs::{FsError, RetryClass, RetryPolicy};
+
+fn policy(max_attempts: u32, retry_on: Vec<RetryClass>) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(500),
+        max_backoff: Duration::from_secs(3),
+        retry_on,
+    }
+}
+
+#[test]
+fn backoff_doubles_up_to_the_limit() {
+    let policy = policy(10, vec![]);
+    let delays: Vec<_> = (1..=5).map(|attempt| policy.backoff(attempt)).collect();
+    assert_eq!(
+        delays,
+        [500, 1000, 2000, 3000, 3000].map(Duration::from_millis)
+    );
+    assert_eq!(policy.backoff(100), Duration::from_secs(3));
+}
+
+#[test]
+fn only_listed_classes_are_retried_within_attempts() {
+    let policy = policy(3, vec![RetryClass::DataConnection]);
+    let data = || FsError::DataConnection {
+        code: 425,
+        message: "Can't open data connection".into(),
+    };
+
+    assert!(policy.should_retry(&data(), 1));
+    assert!(policy.should_retry(&data(), 2));
+    assert!(!policy.should_retry(&data(), 3), "попытки кончились");
+    let busy = FsError::FileBusy {
+        path: "a.txt".into(),
+        code: 450,
+    };
+    assert!(!policy.should_retry(&busy, 1));
+    let missing = FsError::PathNotFound {
+        path: "a.txt".into(),
+        code: Some(550),
+    };
+    assert!(!policy.should_retry(&missing, 1));
+
+    let reset = FsError::Io(io::Error::from(io::ErrorKind::ConnectionReset));
+    assert!(policy.should_retry(&reset, 1), "обрыв канала данных");
+    let disk = FsError::Io(io::Error::from(io::ErrorKind::PermissionDenied));
+    assert!(!policy.should_retry(&disk, 1));
+}
+
+#[test]
+fn lost_connection_is_its_own_class() {
+    assert_eq!(
+        FsError::ServiceClosing("Timeout".into()).retry_class(),
+        Some(RetryClass::ConnectionLost)
+    );
+    assert!(!RetryPolicy::none().should_retry(&FsError::ConnectionLost, 1));
+}
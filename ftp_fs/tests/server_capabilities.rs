@@ -0,0 +1,38 @@
+use ftp_fs::ServerCapabilities;
+
+fn feature(name: &str, params: Option<&str>) -> (String, Option<String>) {
+    (name.to_string(), params.map(str::to_string))
+}
+
+#[test]
+fn parses_known_features_and_keeps_the_rest() {
+    let capabilities = ServerCapabilities::from_features([
+        feature("MDTM", None),
+        feature("mfmt", None),
+        feature("SIZE", None),
+        feature("REST", Some("STREAM")),
+        feature("UTF8", None),
+        feature("EPSV", None),
+        feature("MLST", Some("type*;size*;modify*;")),
+        feature("HASH", Some("SHA-256*;SHA-1;MD5")),
+        feature("TVFS", None),
+        feature("AUTH", Some("TLS")),
+    ]);
+
+    assert!(capabilities.feat);
+    assert!(capabilities.mdtm && capabilities.mfmt && capabilities.size);
+    assert!(capabilities.rest_stream && capabilities.utf8 && capabilities.epsv);
+    assert!(capabilities.mlsd, "MLST объявляет и MLSD");
+    assert_eq!(capabilities.hash, ["SHA-256", "SHA-1", "MD5"]);
+    assert_eq!(capabilities.other, ["AUTH TLS", "TVFS"]);
+}
+
+#[test]
+fn rest_without_stream_is_not_resume_support() {
+    let capabilities = ServerCapabilities::from_features([feature("REST", None)]);
+    assert!(!capabilities.rest_stream);
+    assert_eq!(capabilities.other, ["REST"]);
+
+    let unknown = ServerCapabilities::default();
+    assert!(!unknown.feat, "без ответа на FEAT возможности неизвестны");
+}
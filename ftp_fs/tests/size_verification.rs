@@ -0,0 +1,297 @@
+//! Проверка размера после передачи на сервере, который не знает SIZE:
+//! ненастоящий FTP-сервер в том же процессе объявляет в FEAT только UTF8.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use ftp_fs::{
+    CancelFlags, FsError, FtpConnectParams, FtpFs, TempNamePattern, TransferEvent, TransferOptions,
+    TransferOutcome, TransferReport,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+/// Файлы и принятые команды ненастоящего сервера.
+#[derive(Default)]
+struct Server {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+    /// Строки листинга LIST; без строки файл в листинге не виден.
+    listing: Mutex<Vec<String>>,
+    commands: Mutex<Vec<String>>,
+}
+
+impl Server {
+    async fn start(self: &Arc<Self>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(Arc::clone(&server).session(socket));
+            }
+        });
+        port
+    }
+
+    fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    async fn session(self: Arc<Self>, socket: TcpStream) {
+        let (read, mut write) = socket.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut passive: Option<TcpListener> = None;
+        let mut rename_from = None;
+        write.write_all(b"220 ready\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let (command, arg) = line.split_once(' ').unwrap_or((&line, ""));
+            let command = command.to_ascii_uppercase();
+            self.commands.lock().unwrap().push(command.clone());
+            let reply = match command.as_str() {
+                "USER" => "331 password".to_string(),
+                "PASS" => "230 logged in".to_string(),
+                "TYPE" => "200 type set".to_string(),
+                "FEAT" => "211-Features:\r\n UTF8\r\n211 End".to_string(),
+                "SYST" => "215 UNIX Type: L8".to_string(),
+                "PWD" => "257 \"/\"".to_string(),
+                "PASV" => {
+                    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                    let port = listener.local_addr().unwrap().port();
+                    passive = Some(listener);
+                    format!(
+                        "227 Entering Passive Mode (127,0,0,1,{},{})",
+                        port >> 8,
+                        port & 0xff
+                    )
+                }
+                "LIST" | "RETR" | "STOR" => {
+                    let listener = passive.take().unwrap();
+                    write.write_all(b"150 opening data\r\n").await.unwrap();
+                    let (mut data, _) = listener.accept().await.unwrap();
+                    match command.as_str() {
+                        "LIST" => {
+                            let listing = self.listing.lock().unwrap().concat();
+                            data.write_all(listing.as_bytes()).await.unwrap();
+                        }
+                        "RETR" => {
+                            let content = self.files.lock().unwrap()[arg].clone();
+                            data.write_all(&content).await.unwrap();
+                        }
+                        _ => {
+                            let mut content = Vec::new();
+                            data.read_to_end(&mut content).await.unwrap();
+                            self.files.lock().unwrap().insert(arg.to_string(), content);
+                        }
+                    }
+                    drop(data);
+                    "226 done".to_string()
+                }
+                "RNFR" => {
+                    rename_from = Some(arg.to_string());
+                    "350 ready".to_string()
+                }
+                "RNTO" => {
+                    let mut files = self.files.lock().unwrap();
+                    let content = files.remove(&rename_from.take().unwrap()).unwrap();
+                    files.insert(arg.to_string(), content);
+                    "250 renamed".to_string()
+                }
+                "DELE" => {
+                    self.files.lock().unwrap().remove(arg);
+                    "250 deleted".to_string()
+                }
+                "QUIT" => {
+                    let _ = write.write_all(b"221 bye\r\n").await;
+                    return;
+                }
+                _ => "502 not implemented".to_string(),
+            };
+            if write
+                .write_all(format!("{reply}\r\n").as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// Временная директория теста; удаляется при завершении.
+struct TempRoot(PathBuf);
+
+impl TempRoot {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ftp_fs_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+async fn connect(server: &Arc<Server>) -> FtpFs {
+    let port = server.start().await;
+    FtpFs::connect(FtpConnectParams::new("127.0.0.1", port, "user", "secret"))
+        .await
+        .unwrap()
+}
+
+/// Собрать отчёт из событий передачи.
+async fn report(mut rx: mpsc::Receiver<TransferEvent>) -> TransferReport {
+    let mut report = TransferReport::default();
+    while let Some(event) = rx.recv().await {
+        report.record(event);
+    }
+    report
+}
+
+fn listing_line(name: &str, size: usize) -> String {
+    format!("-rw-r--r-- 1 owner group {size} Jan 01 2024 {name}\r\n")
+}
+
+#[tokio::test]
+async fn download_without_size_checks_the_listing() {
+    let server = Arc::new(Server::default());
+    server
+        .files
+        .lock()
+        .unwrap()
+        .insert("a.txt".into(), b"hello world".to_vec());
+    server
+        .listing
+        .lock()
+        .unwrap()
+        .push(listing_line("a.txt", 11));
+    let mut ftp = connect(&server).await;
+    let tmp = TempRoot::new("size_listing");
+
+    let (tx, rx) = mpsc::channel(64);
+    let options = TransferOptions::default();
+    ftp.download(&tmp.0, &["a.txt".into()], &options, tx, &CancelFlags::new())
+        .await
+        .unwrap();
+    let report = report(rx).await;
+
+    let item = &report.items[0];
+    assert!(
+        matches!(item.outcome, TransferOutcome::Transferred),
+        "{item:?}"
+    );
+    assert!(item.verified);
+    assert_eq!(std::fs::read(tmp.0.join("a.txt")).unwrap(), b"hello world");
+    assert!(!tmp.0.join("a.txt.part").exists());
+    assert!(!server.commands().contains(&"SIZE".to_string()));
+}
+
+#[tokio::test]
+async fn download_without_any_size_is_kept_unverified() {
+    let server = Arc::new(Server::default());
+    server
+        .files
+        .lock()
+        .unwrap()
+        .insert("a.txt".into(), b"hello world".to_vec());
+    let mut ftp = connect(&server).await;
+    let tmp = TempRoot::new("size_unknown");
+
+    let (tx, rx) = mpsc::channel(64);
+    let options = TransferOptions::default();
+    ftp.download(&tmp.0, &["a.txt".into()], &options, tx, &CancelFlags::new())
+        .await
+        .unwrap();
+    let report = report(rx).await;
+
+    let item = &report.items[0];
+    assert!(
+        matches!(item.outcome, TransferOutcome::Transferred),
+        "{item:?}"
+    );
+    assert!(!item.verified);
+    assert_eq!(std::fs::read(tmp.0.join("a.txt")).unwrap(), b"hello world");
+}
+
+#[tokio::test]
+async fn download_shorter_than_listing_is_discarded() {
+    let server = Arc::new(Server::default());
+    server
+        .files
+        .lock()
+        .unwrap()
+        .insert("a.txt".into(), b"hello".to_vec());
+    server
+        .listing
+        .lock()
+        .unwrap()
+        .push(listing_line("a.txt", 11));
+    let mut ftp = connect(&server).await;
+    let tmp = TempRoot::new("size_short");
+    std::fs::write(tmp.0.join("a.txt"), b"old").unwrap();
+
+    let (tx, rx) = mpsc::channel(64);
+    let options = TransferOptions::default();
+    ftp.download(&tmp.0, &["a.txt".into()], &options, tx, &CancelFlags::new())
+        .await
+        .unwrap();
+    let report = report(rx).await;
+
+    assert!(
+        matches!(
+            report.items[0].outcome,
+            TransferOutcome::Failed(FsError::SizeMismatch {
+                expected: 11,
+                actual: 5,
+                ..
+            })
+        ),
+        "{:?}",
+        report.items[0]
+    );
+    assert_eq!(std::fs::read(tmp.0.join("a.txt")).unwrap(), b"old");
+    assert!(!tmp.0.join("a.txt.part").exists());
+}
+
+#[tokio::test]
+async fn atomic_upload_without_size_is_renamed_into_place() {
+    let server = Arc::new(Server::default());
+    let mut ftp = connect(&server).await;
+    let tmp = TempRoot::new("size_upload");
+    std::fs::write(tmp.0.join("b.txt"), b"new content").unwrap();
+
+    let (tx, rx) = mpsc::channel(64);
+    let options = TransferOptions {
+        atomic_upload: Some(TempNamePattern::new(".{name}.uploading").unwrap()),
+        ..TransferOptions::default()
+    };
+    ftp.upload(&tmp.0, &["b.txt".into()], &options, tx, &CancelFlags::new())
+        .await
+        .unwrap();
+    let report = report(rx).await;
+
+    let item = &report.items[0];
+    assert!(
+        matches!(item.outcome, TransferOutcome::Transferred),
+        "{item:?}"
+    );
+    assert!(!item.verified);
+    let files = server.files.lock().unwrap();
+    assert_eq!(
+        files.get("b.txt").map(Vec::as_slice),
+        Some(&b"new content"[..])
+    );
+    assert!(!files.contains_key(".b.txt.uploading"));
+    let commands = server.commands();
+    assert!(!commands.contains(&"SIZE".to_string()));
+    assert!(commands.contains(&"RNTO".to_string()));
+}
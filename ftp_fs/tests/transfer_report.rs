@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use ftp_fs::{FsError, SkipReason, TransferEvent, TransferOutcome, TransferReport};
+
+fn record_all(events: Vec<TransferEvent>) -> TransferReport {
+    let mut report = TransferReport::default();
+    for event in events {
+        report.record(event);
+    }
+    report
+}
+
+#[test]
+fn terminal_events_become_items_in_order() {
+    let report = record_all(vec![
+        TransferEvent::Started {
+            index: 0,
+            name: "a.txt".into(),
+            total: Some(6),
+        },
+        TransferEvent::Progress {
+            index: 0,
+            bytes: 6,
+            total: Some(6),
+        },
+        TransferEvent::Verified { index: 0, size: 6 },
+        TransferEvent::Completed {
+            index: 0,
+            name: "a.txt".into(),
+            bytes: 6,
+            duration: Duration::from_millis(5),
+        },
+        TransferEvent::Skipped {
+            index: 1,
+            name: "docs".into(),
+            reason: SkipReason::Directory,
+        },
+        TransferEvent::Renamed {
+            index: 2,
+            target: "b (1).txt".into(),
+        },
+        TransferEvent::Started {
+            index: 2,
+            name: "b.txt".into(),
+            total: None,
+        },
+        TransferEvent::Retrying {
+            index: 2,
+            attempt: 2,
+            delay: Duration::from_secs(1),
+            error: FsError::FileBusy {
+                path: "b.txt".into(),
+                code: 450,
+            },
+        },
+        TransferEvent::Completed {
+            index: 2,
+            name: "b.txt".into(),
+            bytes: 3,
+            duration: Duration::from_millis(1),
+        },
+    ]);
+
+    let names: Vec<_> = report.items.iter().map(|i| i.name.as_str()).collect();
+    assert_eq!(names, ["a.txt", "docs", "b.txt"]);
+    assert!(report.items[0].verified);
+    assert!(
+        !report.items[2].verified,
+        "Verified относится только к своему файлу"
+    );
+    let attempts: Vec<_> = report.items.iter().map(|i| i.attempts).collect();
+    assert_eq!(attempts, [1, 0, 2]);
+    assert_eq!(report.items[2].renamed.as_deref(), Some("b (1).txt"));
+    assert!(report.items[0].renamed.is_none());
+    assert_eq!(report.bytes(), 9);
+    assert_eq!((report.transferred(), report.skipped()), (2, 1));
+    assert!(report.is_success());
+}
+
+#[test]
+fn lost_connection_is_reported() {
+    let report = record_all(vec![
+        TransferEvent::Failed {
+            index: 0,
+            name: "a.txt".into(),
+            error: FsError::ConnectionLost,
+        },
+        TransferEvent::Skipped {
+            index: 1,
+            name: "b.txt".into(),
+            reason: SkipReason::Aborted,
+        },
+    ]);
+
+    assert!(report.connection_lost());
+    assert!(!report.is_success());
+    assert!(matches!(
+        report.items[1].outcome,
+        TransferOutcome::Skipped(SkipReason::Aborted)
+    ));
+}
@@ -0,0 +1,525 @@
+//! JSON API `/api/v1` для скриптов и CI — те же операции, что и HTMX-маршруты.
+//!
+//! Ошибки отдаются с настоящим HTTP-статусом и телом
+//! `{"error": {"code": ..., "message": ...}}`. Спецификация OpenAPI 3
+//! строится из тех же аннотаций `#[utoipa::path]`, что и маршруты, и
+//! отдаётся по `/api/v1/openapi.json`; её снимок лежит в `openapi.json`.
+
+use std::net::SocketAddr;
+
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, FromRequest, Path, Request, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use ftp_fs::{FileEntry, FileSystem, FsError, ServerCapabilities, TransferOptions};
+use serde::{Serialize, de::DeserializeOwned};
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    conflicts::ConflictMode,
+    error::{AppError, ErrorBody},
+    routes::{self, ChangeDirectoryForm, ConnectForm, DownloadForm, UploadForm},
+    state::AppState,
+    transfers::{Direction, TransferJob},
+};
+
+/// Общая часть спецификации; пути в ней относительны `/api/v1`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "web_ftp_client API", description = "JSON API веб-интерфейса FTP-клиента. Изменяющие запросы, даже без тела, обязаны прийти с Content-Type: application/json, иначе 415."),
+    servers((url = "/api/v1")),
+    tags(
+        (name = "connection", description = "Подключение к FTP-серверу"),
+        (name = "files", description = "Навигация по удалённой и локальной ФС"),
+        (name = "transfers", description = "Фоновые передачи файлов"),
+    )
+)]
+struct ApiDoc;
+
+fn api_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(status))
+        .routes(routes!(connect))
+        .routes(routes!(disconnect))
+        .routes(routes!(server))
+        .routes(routes!(remote_list))
+        .routes(routes!(remote_cd))
+        .routes(routes!(local_list))
+        .routes(routes!(local_cd))
+        .routes(routes!(upload))
+        .routes(routes!(download))
+        .routes(routes!(transfers))
+        .routes(routes!(transfer))
+}
+
+/// Маршруты API; монтируются в `main.rs` под `/api/v1`.
+pub fn router() -> Router<AppState> {
+    let (router, mut spec) = api_routes().split_for_parts();
+    // В Cargo.toml нет лицензии, а пустое имя лицензии OpenAPI не допускает
+    spec.info.license = None;
+    let spec = spec
+        .to_pretty_json()
+        .expect("спецификация OpenAPI сериализуется в JSON");
+    router
+        .route(
+            "/openapi.json",
+            get(move || {
+                let spec = spec.clone();
+                async move { ([(header::CONTENT_TYPE, "application/json")], spec) }
+            }),
+        )
+        .fallback(not_found)
+}
+
+// ---------------------------------------------------------------------------
+// Ответы и ошибки
+// ---------------------------------------------------------------------------
+
+/// Ошибка API: [`AppError`], отданная как JSON.
+pub struct ApiError(AppError);
+
+impl From<AppError> for ApiError {
+    fn from(e: AppError) -> Self {
+        Self(e)
+    }
+}
+
+impl From<FsError> for ApiError {
+    fn from(e: FsError) -> Self {
+        Self(e.into())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.0.status();
+        let body = Json(ErrorEnvelope {
+            error: ErrorBody::from(&self.0),
+        });
+        match self.0 {
+            AppError::Throttled(left) => {
+                let retry = left.as_secs_f64().ceil().to_string();
+                (status, [(header::RETRY_AFTER, retry)], body).into_response()
+            }
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+/// [`Json`], который при неверном теле отвечает ошибкой API, а не текстом.
+pub struct ApiJson<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(rejection) => Err(AppError::BadRequest(rejection.body_text()).into()),
+        }
+    }
+}
+
+/// Состояние сервера.
+#[derive(Serialize, ToSchema)]
+pub struct Status {
+    pub connected: bool,
+    pub connection_error: Option<String>,
+    /// Файл, который передаётся прямо сейчас.
+    pub transfer: Option<String>,
+    pub running_transfers: usize,
+}
+
+/// Содержимое текущей директории.
+#[derive(Serialize, ToSchema)]
+pub struct Listing {
+    pub path: String,
+    pub files: Vec<FileEntry>,
+}
+
+async fn current_status(state: &AppState) -> Status {
+    let status = state.status.get();
+    Status {
+        connected: status.connected,
+        connection_error: status.connection_error,
+        transfer: status.transfer,
+        running_transfers: state.transfers.running().await,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Обработчики
+// ---------------------------------------------------------------------------
+
+/// Подключение, текущая передача и число выполняющихся передач.
+#[utoipa::path(get, path = "/status", tag = "connection",
+    responses((status = OK, body = Status)))]
+async fn status(State(state): State<AppState>) -> Json<Status> {
+    Json(current_status(&state).await)
+}
+
+/// Подключение к FTP-серверу; пустые логин и пароль — из настроенного `.netrc`.
+#[utoipa::path(post, path = "/connect", tag = "connection",
+    request_body = ConnectForm,
+    responses(
+        (status = OK, description = "Подключено", body = Status),
+        (status = BAD_REQUEST, description = "Некорректное тело запроса", body = ErrorEnvelope),
+        (status = FORBIDDEN, description = "Адрес запрещён политикой сервера", body = ErrorEnvelope),
+        (status = TOO_MANY_REQUESTS, description = "Слишком много неудачных попыток", body = ErrorEnvelope,
+            headers(("Retry-After" = u64, description = "Через сколько секунд повторить"))),
+        (status = BAD_GATEWAY, description = "Ошибка FTP-сервера", body = ErrorEnvelope),
+        (status = GATEWAY_TIMEOUT, description = "Сервер не ответил", body = ErrorEnvelope),
+    ))]
+async fn connect(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    ApiJson(form): ApiJson<ConnectForm>,
+) -> Result<Json<Status>, ApiError> {
+    let params = routes::connect_params(&state, form);
+    match routes::establish_connection(&state, client, params).await {
+        Ok(ftp) => {
+            // Своих корней у API нет: подключение лишь отзывает их у сессий
+            routes::switch_local_root(&state, None, None).await;
+            let server = ftp.capabilities().clone();
+            *state.ftp.lock().await = Some(ftp);
+            state.status.set_connected(server);
+        }
+        Err(e) => {
+            state.status.set_error(e.to_string());
+            return Err(e.into());
+        }
+    }
+    Ok(Json(current_status(&state).await))
+}
+
+/// Отключение от FTP-сервера.
+#[utoipa::path(post, path = "/disconnect", tag = "connection",
+    request_body(content_type = "application/json", description = "Тело не нужно, но Content-Type: application/json обязателен"),
+    responses(
+        (status = NO_CONTENT, description = "Соединение закрыто"),
+        (status = UNSUPPORTED_MEDIA_TYPE, description = "Нет Content-Type: application/json"),
+    )
+)]
+async fn disconnect(State(state): State<AppState>) -> StatusCode {
+    let ftp = state.ftp.lock().await.take();
+    state.status.set_disconnected(None);
+    routes::switch_local_root(&state, None, None).await;
+
+    if let Some(ftp) = ftp
+        && let Err(e) = ftp.disconnect().await
+    {
+        log::warn!("Ошибка при отключении: {}", e);
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// Приветствие, тип системы и возможности FTP-сервера, выясненные при входе.
+#[utoipa::path(get, path = "/server", tag = "connection",
+    responses(
+        (status = OK, body = ServerCapabilities),
+        (status = CONFLICT, description = "Нет соединения", body = ErrorEnvelope),
+    ))]
+async fn server(State(state): State<AppState>) -> Result<Json<ServerCapabilities>, ApiError> {
+    let server = state.status.server().ok_or(AppError::NotConnected)?;
+    Ok(Json(server))
+}
+
+/// Список файлов удалённой ФС.
+#[utoipa::path(get, path = "/remote", tag = "files",
+    responses(
+        (status = OK, body = Listing),
+        (status = CONFLICT, description = "Нет соединения", body = ErrorEnvelope),
+        (status = BAD_GATEWAY, description = "Ошибка FTP-сервера", body = ErrorEnvelope),
+    ))]
+async fn remote_list(State(state): State<AppState>) -> Result<Json<Listing>, ApiError> {
+    let mut guard = state.ftp.lock().await;
+    let ftp = guard.as_mut().ok_or(AppError::NotConnected)?;
+    let files = routes::with_reconnect!(state, ftp, ftp.list().await)?;
+    let path = ftp.pwd().await?;
+    Ok(Json(Listing { path, files }))
+}
+
+/// Смена удалённой директории; возвращает её содержимое.
+#[utoipa::path(post, path = "/remote/cd", tag = "files",
+    request_body = ChangeDirectoryForm,
+    responses(
+        (status = OK, body = Listing),
+        (status = CONFLICT, description = "Нет соединения", body = ErrorEnvelope),
+        (status = BAD_GATEWAY, description = "Ошибка FTP-сервера", body = ErrorEnvelope),
+    ))]
+async fn remote_cd(
+    State(state): State<AppState>,
+    ApiJson(form): ApiJson<ChangeDirectoryForm>,
+) -> Result<Json<Listing>, ApiError> {
+    let mut guard = state.ftp.lock().await;
+    let ftp = guard.as_mut().ok_or(AppError::NotConnected)?;
+    routes::with_reconnect!(state, ftp, ftp.change_dir(&form.directory).await)?;
+    let files = ftp.list().await?;
+    let path = ftp.pwd().await?;
+    Ok(Json(Listing { path, files }))
+}
+
+/// Список файлов локальной ФС.
+#[utoipa::path(get, path = "/local", tag = "files",
+    responses((status = OK, body = Listing)))]
+async fn local_list(State(state): State<AppState>) -> Result<Json<Listing>, ApiError> {
+    let mut local = state.local_fs.lock().await;
+    let files = local.list().await?;
+    let path = local.current_dir().await?;
+    Ok(Json(Listing { path, files }))
+}
+
+/// Смена локальной директории; возвращает её содержимое.
+#[utoipa::path(post, path = "/local/cd", tag = "files",
+    request_body = ChangeDirectoryForm,
+    responses(
+        (status = OK, body = Listing),
+        (status = FORBIDDEN, description = "Путь за пределами корня", body = ErrorEnvelope),
+        (status = NOT_FOUND, description = "Директория не найдена", body = ErrorEnvelope),
+    ))]
+async fn local_cd(
+    State(state): State<AppState>,
+    ApiJson(form): ApiJson<ChangeDirectoryForm>,
+) -> Result<Json<Listing>, ApiError> {
+    let mut local = state.local_fs.lock().await;
+    local.change_dir(&form.directory).await?;
+    let files = local.list().await?;
+    let path = local.current_dir().await?;
+    Ok(Json(Listing { path, files }))
+}
+
+/// Запустить загрузку на сервер; отвечает `202` и описанием передачи.
+#[utoipa::path(post, path = "/upload", tag = "transfers",
+    request_body = UploadForm,
+    responses(
+        (status = ACCEPTED, description = "Передача запущена", body = TransferJob,
+            headers(("Location" = String, description = "Адрес описания передачи"))),
+        (status = BAD_REQUEST, description = "Пустой список, недопустимое имя или параметры повторов", body = ErrorEnvelope),
+        (status = CONFLICT, description = "Нет соединения", body = ErrorEnvelope),
+    ))]
+async fn upload(
+    State(state): State<AppState>,
+    ApiJson(form): ApiJson<UploadForm>,
+) -> Result<Response, ApiError> {
+    let options = routes::transfer_options(&state, form.retry, form.limit_kb_per_sec);
+    start_transfer(state, form.files, options, form.conflict, Direction::Upload).await
+}
+
+/// Запустить скачивание с сервера; отвечает `202` и описанием передачи.
+#[utoipa::path(post, path = "/download", tag = "transfers",
+    request_body = DownloadForm,
+    responses(
+        (status = ACCEPTED, description = "Передача запущена", body = TransferJob,
+            headers(("Location" = String, description = "Адрес описания передачи"))),
+        (status = BAD_REQUEST, description = "Пустой список, недопустимое имя или параметры повторов", body = ErrorEnvelope),
+        (status = CONFLICT, description = "Нет соединения", body = ErrorEnvelope),
+    ))]
+async fn download(
+    State(state): State<AppState>,
+    ApiJson(form): ApiJson<DownloadForm>,
+) -> Result<Response, ApiError> {
+    let options = routes::transfer_options(&state, form.retry, form.limit_kb_per_sec);
+    start_transfer(
+        state,
+        form.files,
+        options,
+        form.conflict,
+        Direction::Download,
+    )
+    .await
+}
+
+/// Проверки, которые можно сделать до запуска: пустой список, имена файлов,
+/// параметры повторов и наличие соединения. Остальные ошибки попадут в
+/// описание передачи.
+async fn start_transfer(
+    state: AppState,
+    files: Vec<String>,
+    options: Result<TransferOptions, AppError>,
+    conflict: Option<ConflictMode>,
+    direction: Direction,
+) -> Result<Response, ApiError> {
+    if files.is_empty() {
+        return Err(AppError::BadRequest("список файлов пуст".to_string()).into());
+    }
+    let files = ftp_fs::validate_file_names(&files)?;
+    let options = options?;
+    if !state.status.is_connected() {
+        return Err(AppError::NotConnected.into());
+    }
+
+    let job = state
+        .transfers
+        .spawn(state.clone(), direction, files, options, conflict)
+        .await;
+    let location = format!("/api/v1/transfers/{}", job.id);
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        Json(job),
+    )
+        .into_response())
+}
+
+/// Запущенные и недавно завершённые передачи.
+#[utoipa::path(get, path = "/transfers", tag = "transfers",
+    responses((status = OK, body = Vec<TransferJob>)))]
+async fn transfers(State(state): State<AppState>) -> Json<Vec<TransferJob>> {
+    Json(state.transfers.list().await)
+}
+
+/// Одна передача по идентификатору.
+#[utoipa::path(get, path = "/transfers/{id}", tag = "transfers",
+    params(("id" = u64, Path, description = "Идентификатор передачи")),
+    responses(
+        (status = OK, body = TransferJob),
+        (status = NOT_FOUND, description = "Передача не найдена", body = ErrorEnvelope),
+    ))]
+async fn transfer(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<TransferJob>, ApiError> {
+    state
+        .transfers
+        .get(id)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::TransferNotFound(id).into())
+}
+
+async fn not_found() -> Response {
+    let body = ErrorEnvelope {
+        error: ErrorBody {
+            code: "not_found",
+            message: "Неизвестный метод API".to_string(),
+            reply_code: None,
+            path: None,
+        },
+    };
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, path::PathBuf};
+
+    use axum::{
+        body::{Body, to_bytes},
+        extract::connect_info::MockConnectInfo,
+        http::{Method, Request},
+    };
+    use serde_json::Value;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Снимок спецификации, который читают внешние инструменты.
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// Приложение над временным корнем; директория удаляется вместе с
+    /// возвращённым [`TempDir`].
+    fn test_app() -> (Router, TempDir) {
+        let root = TempDir::new().unwrap();
+        let state = AppState::for_tests(root.path());
+        let app = router()
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))))
+            .with_state(state);
+        (app, root)
+    }
+
+    async fn send(app: &Router, method: Method, uri: &str) -> (StatusCode, Value) {
+        let body = if method == Method::GET {
+            Body::empty()
+        } else {
+            Body::from("{}")
+        };
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn served_spec(app: &Router) -> Value {
+        let (status, spec) = send(app, Method::GET, "/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+        spec
+    }
+
+    #[tokio::test]
+    async fn every_documented_operation_is_routed() {
+        let (app, _root) = test_app();
+        let spec = served_spec(&app).await;
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(!paths.is_empty());
+
+        for (path, item) in paths {
+            let uri = path.replace("{id}", "1");
+            for method in item.as_object().unwrap().keys() {
+                let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+                let (status, body) = send(&app, method.clone(), &uri).await;
+                assert_ne!(
+                    status,
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "{method} {path} описан, но не обрабатывается"
+                );
+                assert_ne!(
+                    body["error"]["code"], "not_found",
+                    "{method} {path} описан, но маршрута нет"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn undocumented_paths_fall_through() {
+        let (app, _root) = test_app();
+        let (status, body) = send(&app, Method::GET, "/no-such-method").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "not_found");
+    }
+
+    /// Изменили обработчики или типы — обновите снимок:
+    /// `UPDATE_OPENAPI=1 cargo test` и закоммитьте `openapi.json`.
+    #[tokio::test]
+    async fn served_spec_matches_snapshot() {
+        let (app, _root) = test_app();
+        let spec = served_spec(&app).await;
+        let pretty = serde_json::to_string_pretty(&spec).unwrap() + "\n";
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(SNAPSHOT, pretty).unwrap();
+            return;
+        }
+        let snapshot: Value = std::fs::read_to_string(PathBuf::from(SNAPSHOT))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or(Value::Null);
+        assert!(
+            spec == snapshot,
+            "спецификация разошлась с openapi.json; обновите снимок: UPDATE_OPENAPI=1 cargo test"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Ограничение скорости передач: общий лимит на все передачи сразу,
+//! лимит одной передачи и расписание общего лимита.
+//!
+//! Общий лимит меняется на ходу — по расписанию или из интерфейса — и
+//! сразу действует на уже идущие передачи.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveTime};
+use ftp_fs::RateLimiter;
+use serde::Deserialize;
+
+use crate::status::StatusChannel;
+
+/// Как часто сверять общий лимит с расписанием.
+const SCHEDULE_CHECK: Duration = Duration::from_secs(30);
+
+/// Лимит в КБ/с из конфигурации или формы в байтах в секунду; 0 — без
+/// ограничения.
+pub fn limit_from_kb(kb: u64) -> Option<u64> {
+    (kb > 0).then(|| kb.saturating_mul(1024))
+}
+
+/// Время суток `"ЧЧ:ММ"` в расписании.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(NaiveTime);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&s, "%H:%M")
+            .map(Self)
+            .map_err(|_| format!("ожидается время ЧЧ:ММ, получено {:?}", s))
+    }
+}
+
+/// Интервал расписания: с `from` до `to` по местному времени действует
+/// свой общий лимит. Интервал может переходить через полночь.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    pub from: TimeOfDay,
+    pub to: TimeOfDay,
+    /// КБ/с; 0 — без ограничения.
+    pub limit_kb_per_sec: u64,
+}
+
+impl ScheduleEntry {
+    fn contains(&self, time: NaiveTime) -> bool {
+        let (from, to) = (self.from.0, self.to.0);
+        if from <= to {
+            from <= time && time < to
+        } else {
+            time >= from || time < to
+        }
+    }
+}
+
+/// Ограничение скорости (секция `[transfers.bandwidth]` конфигурации).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthSettings {
+    /// Общий лимит на все передачи вместе, КБ/с; 0 — без ограничения.
+    pub limit_kb_per_sec: u64,
+    /// Лимит одной передачи, если запрос не задал свой, КБ/с; 0 — без
+    /// ограничения.
+    pub per_transfer_kb_per_sec: u64,
+    /// Интервалы с другим общим лимитом; действует первый подходящий.
+    pub schedule: Vec<ScheduleEntry>,
+}
+
+impl BandwidthSettings {
+    /// Общий лимит по расписанию в момент `time`, в байтах в секунду.
+    pub fn scheduled_limit(&self, time: NaiveTime) -> Option<u64> {
+        let kb = self
+            .schedule
+            .iter()
+            .find(|entry| entry.contains(time))
+            .map_or(self.limit_kb_per_sec, |entry| entry.limit_kb_per_sec);
+        limit_from_kb(kb)
+    }
+}
+
+/// Общий ограничитель скорости и источник его лимита.
+pub struct Bandwidth {
+    settings: BandwidthSettings,
+    limiter: RateLimiter,
+    /// Лимит из интерфейса в КБ/с (0 — без ограничения); пока он задан,
+    /// расписание не действует.
+    manual: Mutex<Option<u64>>,
+}
+
+impl Bandwidth {
+    pub fn new(settings: BandwidthSettings) -> Self {
+        let limiter = RateLimiter::new(settings.scheduled_limit(Local::now().time()));
+        Self {
+            settings,
+            limiter,
+            manual: Mutex::new(None),
+        }
+    }
+
+    /// Ограничители для новой передачи: общий и собственный, если задан.
+    ///
+    /// `per_transfer_kb` — лимит из запроса вместо значения из конфигурации.
+    pub fn limiters(&self, per_transfer_kb: Option<u64>) -> Vec<RateLimiter> {
+        let mut limiters = vec![self.limiter.clone()];
+        let kb = per_transfer_kb.unwrap_or(self.settings.per_transfer_kb_per_sec);
+        if let Some(limit) = limit_from_kb(kb) {
+            limiters.push(RateLimiter::new(Some(limit)));
+        }
+        limiters
+    }
+
+    /// Задать общий лимит вручную (КБ/с, 0 — без ограничения) или вернуться
+    /// к расписанию (`None`); возвращает новый лимит.
+    pub fn set_manual(&self, kb: Option<u64>) -> Option<u64> {
+        *self.manual.lock().unwrap() = kb;
+        self.refresh()
+    }
+
+    /// Пересчитать общий лимит по ручному значению или расписанию.
+    pub fn refresh(&self) -> Option<u64> {
+        let limit = match *self.manual.lock().unwrap() {
+            Some(kb) => limit_from_kb(kb),
+            None => self.settings.scheduled_limit(Local::now().time()),
+        };
+        self.limiter.set_limit(limit);
+        limit
+    }
+}
+
+/// Фоновая задача: переключает общий лимит по расписанию и показывает
+/// его в подвале.
+pub fn spawn_scheduler(bandwidth: Arc<Bandwidth>, status: Arc<StatusChannel>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULE_CHECK);
+        loop {
+            interval.tick().await;
+            status.set_bandwidth(bandwidth.refresh());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    fn entry(from: &str, to: &str, limit_kb_per_sec: u64) -> ScheduleEntry {
+        ScheduleEntry {
+            from: TimeOfDay(time(from)),
+            to: TimeOfDay(time(to)),
+            limit_kb_per_sec,
+        }
+    }
+
+    #[test]
+    fn schedule_picks_first_matching_entry() {
+        let settings = BandwidthSettings {
+            limit_kb_per_sec: 100,
+            schedule: vec![
+                entry("09:00", "18:00", 10),
+                entry("12:00", "13:00", 50),
+                entry("22:00", "06:00", 0),
+            ],
+            ..BandwidthSettings::default()
+        };
+
+        assert_eq!(settings.scheduled_limit(time("09:00")), Some(10 * 1024));
+        assert_eq!(settings.scheduled_limit(time("12:30")), Some(10 * 1024));
+        // Конец интервала в него не входит
+        assert_eq!(settings.scheduled_limit(time("18:00")), Some(100 * 1024));
+        // Интервал через полночь; 0 — без ограничения
+        assert_eq!(settings.scheduled_limit(time("23:00")), None);
+        assert_eq!(settings.scheduled_limit(time("05:59")), None);
+        assert_eq!(settings.scheduled_limit(time("06:00")), Some(100 * 1024));
+    }
+
+    #[test]
+    fn schedule_time_must_be_hours_and_minutes() {
+        assert!(TimeOfDay::try_from("7:30".to_string()).is_ok());
+        assert!(TimeOfDay::try_from("25:00".to_string()).is_err());
+        assert!(TimeOfDay::try_from("утром".to_string()).is_err());
+    }
+
+    #[test]
+    fn transfer_gets_global_and_own_limiter() {
+        let bandwidth = Bandwidth::new(BandwidthSettings {
+            limit_kb_per_sec: 100,
+            per_transfer_kb_per_sec: 20,
+            schedule: Vec::new(),
+        });
+
+        let limits = |request: Option<u64>| {
+            bandwidth
+                .limiters(request)
+                .iter()
+                .map(RateLimiter::limit)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(limits(None), [Some(100 * 1024), Some(20 * 1024)]);
+        // Лимит из запроса заменяет лимит из конфигурации, 0 снимает его
+        assert_eq!(limits(Some(5)), [Some(100 * 1024), Some(5 * 1024)]);
+        assert_eq!(limits(Some(0)), [Some(100 * 1024)]);
+    }
+
+    #[test]
+    fn manual_limit_overrides_schedule_until_reset() {
+        let bandwidth = Bandwidth::new(BandwidthSettings {
+            limit_kb_per_sec: 100,
+            ..BandwidthSettings::default()
+        });
+        let global = bandwidth.limiters(Some(0)).remove(0);
+
+        assert_eq!(bandwidth.set_manual(Some(10)), Some(10 * 1024));
+        assert_eq!(global.limit(), Some(10 * 1024));
+        assert_eq!(bandwidth.refresh(), Some(10 * 1024));
+
+        assert_eq!(bandwidth.set_manual(Some(0)), None);
+        assert_eq!(global.limit(), None);
+
+        assert_eq!(bandwidth.set_manual(None), Some(100 * 1024));
+        assert_eq!(global.limit(), Some(100 * 1024));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Parser;
+use ftp_fs::{RetryClass, RetryPolicy, TempNamePattern};
+use log::LevelFilter;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    bandwidth::BandwidthSettings, conflicts::ConflictMode, profiles::ProfileSettings,
+    session::SessionSettings, target_policy::TargetPolicy, throttle::ThrottleSettings,
+    tls::TlsSettings,
+};
+
+/// Файл конфигурации по умолчанию (в текущей директории).
+const DEFAULT_CONFIG_FILE: &str = "web_ftp_client.toml";
+
+/// Ошибка загрузки конфигурации.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("не удалось прочитать {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("ошибка в {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+/// Аргументы командной строки.
+///
+/// Каждый аргумент можно задать и переменной окружения; приоритет:
+/// командная строка, окружение, файл конфигурации, значение по умолчанию.
+#[derive(Debug, Parser)]
+#[command(version, about = "Веб-интерфейс FTP-клиента", long_about = None)]
+pub struct Cli {
+    /// TOML-файл конфигурации (по умолчанию web_ftp_client.toml, если есть).
+    #[arg(short, long, env = "WEB_FTP_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Адрес, на котором слушает веб-сервер.
+    #[arg(long, env = "WEB_FTP_BIND")]
+    pub bind: Option<IpAddr>,
+
+    /// Порт веб-сервера.
+    #[arg(short, long, env = "WEB_FTP_PORT")]
+    pub port: Option<u16>,
+
+    /// Корень локальной ФС, за который нельзя выйти из UI.
+    #[arg(long, env = "WEB_FTP_LOCAL_ROOT")]
+    pub local_root: Option<PathBuf>,
+
+    /// Директория со статикой (CSS, JS, шрифты).
+    #[arg(long, env = "WEB_FTP_ASSETS_DIR")]
+    pub assets_dir: Option<PathBuf>,
+
+    /// Таймаут подключения к FTP-серверу по умолчанию, в секундах.
+    #[arg(long, env = "WEB_FTP_CONNECT_TIMEOUT")]
+    pub connect_timeout: Option<u64>,
+
+    /// Уровень логирования: off, error, warn, info, debug, trace.
+    #[arg(long, env = "WEB_FTP_LOG_LEVEL")]
+    pub log_level: Option<LevelFilter>,
+
+    /// Время жизни простаивающей сессии, в секундах.
+    #[arg(long, env = "WEB_FTP_SESSION_IDLE_TIMEOUT")]
+    pub session_idle_timeout: Option<u64>,
+}
+
+/// Настройки HTTP-сервера (секция `[server]`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Корень локальной ФС. По умолчанию — текущая директория.
+    pub local_root: Option<PathBuf>,
+    /// Свои корни локальной ФС по ключу `логин@хост`: браузерная сессия,
+    /// подключившаяся под этим логином к этому серверу, видит только свой
+    /// корень, остальные — `local_root`.
+    pub user_roots: HashMap<String, PathBuf>,
+    pub assets_dir: PathBuf,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::from([0, 0, 0, 0]),
+            port: 3000,
+            local_root: None,
+            user_roots: HashMap::new(),
+            assets_dir: PathBuf::from("assets"),
+        }
+    }
+}
+
+/// Повторы передачи файла после временных ошибок (секция
+/// `[transfers.retry]`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySettings {
+    /// Сколько всего попыток на файл, включая первую; 1 — без повторов.
+    pub max_attempts: u32,
+    /// Пауза перед первым повтором в миллисекундах, дальше вдвое дольше.
+    pub initial_backoff_ms: u64,
+    /// Верхняя граница паузы в миллисекундах.
+    pub max_backoff_ms: u64,
+    /// Какие ошибки повторять: `data_connection`, `file_busy`,
+    /// `connection_lost`.
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+            retry_on: self.retry_on.clone(),
+        }
+    }
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        let policy = RetryPolicy::default();
+        Self {
+            max_attempts: policy.max_attempts,
+            initial_backoff_ms: policy.initial_backoff.as_millis() as u64,
+            max_backoff_ms: policy.max_backoff.as_millis() as u64,
+            retry_on: policy.retry_on,
+        }
+    }
+}
+
+/// Параметры передачи файлов по умолчанию (секция `[transfers]`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransferSettings {
+    /// Что делать с файлами, которые уже есть в месте назначения, если
+    /// запрос не задал своё.
+    pub conflict: ConflictMode,
+    /// Загружать файлы под временным именем и переименовывать на место
+    /// после проверки.
+    pub atomic_uploads: bool,
+    /// Шаблон временного имени; `{name}` заменяется настоящим именем.
+    pub upload_temp_name: TempNamePattern,
+    pub retry: RetrySettings,
+    pub bandwidth: BandwidthSettings,
+}
+
+/// Конфигурация веб-сервера.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub log_level: LevelFilter,
+    /// Таймаут подключения из формы `/connect`, в секундах.
+    pub connect_timeout_secs: u64,
+    /// Файл `.netrc` для подстановки логина и пароля, если в форме они
+    /// пустые. Без этой настройки `.netrc` не читается.
+    pub netrc_file: Option<PathBuf>,
+    /// Брать учётные данные из записи `default` для хостов без своей
+    /// записи `machine`. Выключено: иначе любой адрес из формы получил
+    /// бы эти логин и пароль.
+    pub netrc_use_default: bool,
+    pub server: ServerSettings,
+    /// HTTPS; без этой секции сервер работает по HTTP.
+    pub tls: Option<TlsSettings>,
+    /// Cookie и время жизни браузерных сессий.
+    pub session: SessionSettings,
+    /// Правила допустимых адресов FTP-серверов.
+    pub targets: TargetPolicy,
+    /// Защита `/connect` от перебора паролей.
+    pub throttle: ThrottleSettings,
+    /// Где хранятся профили подключений и их пароли.
+    pub profiles: ProfileSettings,
+    /// Повторы и другие параметры передачи файлов.
+    pub transfers: TransferSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: LevelFilter::Info,
+            connect_timeout_secs: 5,
+            netrc_file: None,
+            netrc_use_default: false,
+            server: ServerSettings::default(),
+            tls: None,
+            session: SessionSettings::default(),
+            targets: TargetPolicy::default(),
+            throttle: ThrottleSettings::default(),
+            profiles: ProfileSettings::default(),
+            transfers: TransferSettings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Собрать конфигурацию: файл из `--config` (или `web_ftp_client.toml`,
+    /// если он есть), поверх — аргументы и переменные окружения из `cli`.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        config.apply_overrides(cli);
+        Ok(config)
+    }
+
+    /// Прочитать конфигурацию из TOML-файла.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply_overrides(&mut self, cli: &Cli) {
+        if let Some(bind) = cli.bind {
+            self.server.bind = bind;
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if let Some(root) = &cli.local_root {
+            self.server.local_root = Some(root.clone());
+        }
+        if let Some(assets) = &cli.assets_dir {
+            self.server.assets_dir = assets.clone();
+        }
+        if let Some(timeout) = cli.connect_timeout {
+            self.connect_timeout_secs = timeout;
+        }
+        if let Some(level) = cli.log_level {
+            self.log_level = level;
+        }
+        if let Some(idle) = cli.session_idle_timeout {
+            self.session.idle_timeout_secs = idle;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Файлы, которые уже есть в месте назначения: политика передачи и вопрос
+//! пользователю.
+//!
+//! В режиме [`ConflictMode::Ask`] [`crate::routes::handle_transfer`] до
+//! начала передачи находит такие файлы и задаёт вопрос, а SSE-поток
+//! `/events` показывает его диалогом. Ответ (`POST /conflicts/resolve`)
+//! задаёт политику для каждого файла; без ответа за [`ASK_TIMEOUT`]
+//! конфликтующие файлы пропускаются.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use ftp_fs::{Conflict, ConflictPolicy};
+use serde::Deserialize;
+use tokio::sync::{oneshot, watch};
+use utoipa::ToSchema;
+
+use crate::error::AppError;
+
+/// Сколько передача ждёт ответа на вопрос о конфликтах.
+const ASK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Что делать с файлами, которые уже есть в месте назначения.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictMode {
+    /// Перезаписать.
+    #[default]
+    Overwrite,
+    /// Не передавать.
+    Skip,
+    /// Сохранить под свободным именем: `report (1).pdf`.
+    Rename,
+    /// Перезаписать, если источник новее.
+    OverwriteIfNewer,
+    /// Перезаписать, если размеры различаются.
+    OverwriteIfSizeDiffers,
+    /// Спросить в диалоге на странице, перечислив конфликты.
+    Ask,
+}
+
+impl ConflictMode {
+    /// Политика передачи. Для [`ConflictMode::Ask`] — пропуск: так
+    /// поступают с файлами, о которых не спросили, потому что они появились
+    /// уже после вопроса.
+    pub fn policy(self) -> ConflictPolicy {
+        match self {
+            Self::Overwrite => ConflictPolicy::Overwrite,
+            Self::Skip | Self::Ask => ConflictPolicy::Skip,
+            Self::Rename => ConflictPolicy::Rename,
+            Self::OverwriteIfNewer => ConflictPolicy::OverwriteIfNewer,
+            Self::OverwriteIfSizeDiffers => ConflictPolicy::OverwriteIfSizeDiffers,
+        }
+    }
+}
+
+/// Вопрос о конфликтах одной пачки.
+#[derive(Debug, Clone)]
+pub struct ConflictQuestion {
+    pub id: u64,
+    pub is_upload: bool,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Открытые вопросы о конфликтах и ожидающие ответа передачи.
+pub struct ConflictPrompts {
+    next_id: AtomicU64,
+    tx: watch::Sender<Vec<ConflictQuestion>>,
+    answers: Mutex<HashMap<u64, oneshot::Sender<HashMap<String, ConflictPolicy>>>>,
+}
+
+impl ConflictPrompts {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            tx: watch::Sender::new(Vec::new()),
+            answers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Vec<ConflictQuestion>> {
+        self.tx.subscribe()
+    }
+
+    /// Спросить, что делать с `conflicts`, и дождаться ответа: политики по
+    /// именам файлов. Без ответа за [`ASK_TIMEOUT`] — пустой набор.
+    pub async fn ask(
+        &self,
+        is_upload: bool,
+        conflicts: Vec<Conflict>,
+    ) -> HashMap<String, ConflictPolicy> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (tx, rx) = oneshot::channel();
+        self.answers.lock().unwrap().insert(id, tx);
+        self.tx.send_modify(|questions| {
+            questions.push(ConflictQuestion {
+                id,
+                is_upload,
+                conflicts,
+            })
+        });
+
+        let answer = tokio::time::timeout(ASK_TIMEOUT, rx).await;
+        self.answers.lock().unwrap().remove(&id);
+        self.tx
+            .send_modify(|questions| questions.retain(|q| q.id != id));
+        match answer {
+            Ok(Ok(decisions)) => decisions,
+            _ => {
+                log::info!("Нет ответа о конфликтах, такие файлы будут пропущены");
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Ответить на вопрос `id`.
+    pub fn answer(
+        &self,
+        id: u64,
+        decisions: HashMap<String, ConflictPolicy>,
+    ) -> Result<(), AppError> {
+        let tx =
+            self.answers.lock().unwrap().remove(&id).ok_or_else(|| {
+                AppError::BadRequest("вопрос о конфликтах уже закрыт".to_string())
+            })?;
+        // Передача могла перестать ждать: ответ тогда просто не нужен
+        let _ = tx.send(decisions);
+        Ok(())
+    }
+}
+
+impl Default for ConflictPrompts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
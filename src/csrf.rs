@@ -0,0 +1,279 @@
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+
+use crate::{error::AppError, state::AppState};
+
+/// Заголовок, в котором HTMX присылает CSRF-токен (см. `hx-headers` в `base.html`).
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// CSRF-токен текущей сессии, доступный обработчикам через `Extension`.
+#[derive(Clone)]
+pub struct CsrfToken(pub String);
+
+/// Идентификатор текущей сессии, доступный обработчикам через `Extension`.
+#[derive(Clone)]
+pub struct SessionId(pub String);
+
+/// Middleware: находит сессию запроса и проверяет CSRF-токен у изменяющих
+/// запросов.
+///
+/// Безопасные методы (`GET`, `HEAD`, `OPTIONS`) пропускаются, остальные
+/// обязаны передать в [`CSRF_HEADER`] токен своей сессии, иначе `403`.
+/// Новую сессию middleware не заводит — это делает страница через
+/// [`page_session`], чтобы статика и `/events` не плодили сессии.
+///
+/// JSON API (`/api/`) работает без сессии: вместо токена изменяющие запросы,
+/// даже без тела, обязаны прийти с `Content-Type: application/json`. Такой
+/// запрос с чужой страницы требует CORS preflight, на который сервер не
+/// отвечает; без заголовка `POST` ушёл бы и без preflight.
+pub async fn protect(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    if req.uri().path().starts_with("/api/") {
+        if !safe && !is_json(&req) {
+            log::warn!(
+                "Отклонён запрос {} {} без Content-Type: application/json",
+                req.method(),
+                req.uri()
+            );
+            return (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Ожидается Content-Type: application/json",
+            )
+                .into_response();
+        }
+        return next.run(req).await;
+    }
+
+    let settings = state.sessions.settings();
+    let existing = match jar.get(&settings.cookie_name) {
+        Some(cookie) => {
+            let id = cookie.value().to_string();
+            state.sessions.touch(&id).await.map(|session| (id, session))
+        }
+        None => None,
+    };
+
+    if !safe {
+        let sent = req
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let valid = existing
+            .as_ref()
+            .is_some_and(|(_, s)| constant_time_eq(s.csrf_token.as_bytes(), sent.as_bytes()));
+        if !valid {
+            log::warn!(
+                "Отклонён запрос {} {} без валидного CSRF-токена",
+                req.method(),
+                req.uri()
+            );
+            return AppError::InvalidCsrf.into_response();
+        }
+    }
+
+    if let Some((id, session)) = existing {
+        req.extensions_mut().insert(CsrfToken(session.csrf_token));
+        req.extensions_mut().insert(SessionId(id));
+    }
+    next.run(req).await
+}
+
+/// CSRF-токен для отрисовки страницы: из сессии запроса или из новой
+/// сессии, cookie которой добавляется в `jar`.
+pub async fn page_session(
+    state: &AppState,
+    jar: CookieJar,
+    token: Option<CsrfToken>,
+) -> (CookieJar, CsrfToken) {
+    if let Some(token) = token {
+        return (jar, token);
+    }
+    let settings = state.sessions.settings();
+    let (id, session) = state.sessions.create().await;
+    let cookie = Cookie::build((settings.cookie_name.clone(), id))
+        .path("/")
+        .http_only(true)
+        .secure(settings.secure_cookie)
+        .same_site(SameSite::Strict);
+    (jar.add(cookie), CsrfToken(session.csrf_token))
+}
+
+fn is_json(req: &Request) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Сравнение без раннего выхода, чтобы не раскрывать токен по времени ответа.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension, Router,
+        body::Body,
+        http::{Request, StatusCode, header},
+        middleware,
+        routing::{get, post},
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn test_app(root: &std::path::Path) -> Router {
+        let state = AppState::for_tests(root);
+        Router::new()
+            .route(
+                "/",
+                get(
+                    |State(state): State<AppState>,
+                     jar: CookieJar,
+                     token: Option<Extension<CsrfToken>>| async move {
+                        let token = token.map(|Extension(token)| token);
+                        let (jar, CsrfToken(token)) = page_session(&state, jar, token).await;
+                        (jar, token)
+                    },
+                ),
+            )
+            .route("/events", get(|| async { StatusCode::OK }))
+            .route("/change", post(|| async { StatusCode::NO_CONTENT }))
+            .route("/api/v1/change", post(|| async { StatusCode::NO_CONTENT }))
+            .layer(middleware::from_fn_with_state(state.clone(), protect))
+            .with_state(state)
+    }
+
+    /// Открыть страницу: cookie сессии и её CSRF-токен.
+    async fn open_session(app: &Router) -> (String, String) {
+        let resp = app
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (cookie, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn send(app: &Router, uri: &str, headers: &[(&str, &str)]) -> StatusCode {
+        let mut req = Request::post(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let resp = app
+            .clone()
+            .oneshot(req.body(Body::from("{}")).unwrap())
+            .await
+            .unwrap();
+        resp.status()
+    }
+
+    #[tokio::test]
+    async fn state_changing_request_needs_the_session_token() {
+        let root = tempfile::tempdir().unwrap();
+        let app = test_app(root.path());
+        let (cookie, token) = open_session(&app).await;
+
+        assert_eq!(send(&app, "/change", &[]).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            send(&app, "/change", &[("cookie", &cookie)]).await,
+            StatusCode::FORBIDDEN,
+            "без токена"
+        );
+        assert_eq!(
+            send(
+                &app,
+                "/change",
+                &[("cookie", &cookie), (CSRF_HEADER, "0badc0de")]
+            )
+            .await,
+            StatusCode::FORBIDDEN,
+            "чужой токен"
+        );
+        assert_eq!(
+            send(&app, "/change", &[(CSRF_HEADER, &token)]).await,
+            StatusCode::FORBIDDEN,
+            "токен без сессии"
+        );
+        assert_eq!(
+            send(
+                &app,
+                "/change",
+                &[("cookie", &cookie), (CSRF_HEADER, &token)]
+            )
+            .await,
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    async fn only_pages_create_sessions() {
+        let root = tempfile::tempdir().unwrap();
+        let app = test_app(root.path());
+
+        let resp = app
+            .clone()
+            .oneshot(Request::get("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!resp.headers().contains_key(header::SET_COOKIE));
+
+        // Повторное открытие страницы остаётся в той же сессии
+        let (cookie, token) = open_session(&app).await;
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::get("/")
+                    .header("cookie", &cookie)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(!resp.headers().contains_key(header::SET_COOKIE));
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, token.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn api_needs_json_instead_of_token() {
+        let root = tempfile::tempdir().unwrap();
+        let app = test_app(root.path());
+
+        let json = [("content-type", "application/json; charset=utf-8")];
+        assert_eq!(
+            send(&app, "/api/v1/change", &json).await,
+            StatusCode::NO_CONTENT
+        );
+        let form = [("content-type", "application/x-www-form-urlencoded")];
+        assert_eq!(
+            send(&app, "/api/v1/change", &form).await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            send(&app, "/api/v1/change", &[]).await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+}
@@ -1,25 +1,261 @@
-use axum::response::{Html, IntoResponse, Response};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use askama::Template;
+use axum::{
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
 use ftp_fs::FsError;
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+    config::ConfigError, profiles::ProfileError, target_policy::TargetError,
+    templates::NotificationTemplate,
+};
 
 /// Глобальная ошибка веб-приложения, оборачивающая внутренние и отдающая HTML.
+///
+/// HTML-ответ приходит с настоящим статусом и заголовками `HX-Retarget` /
+/// `HX-Reswap`, поэтому HTMX показывает его в `#notifications`, а не в цели
+/// запроса.
 #[derive(Debug, Error)]
 pub enum AppError {
-    #[error("Ошибка файловой системы: {0}")]
+    #[error("{}", fs_message(.0))]
     Fs(#[from] FsError),
 
     #[error("Нет активного соединения с сервером")]
     NotConnected,
+
+    #[error("{0}")]
+    Profile(#[from] ProfileError),
+
+    #[error("Некорректный запрос: {0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    Target(#[from] TargetError),
+
+    #[error("Слишком много неудачных попыток, повторите через {} с", .0.as_secs_f64().ceil())]
+    Throttled(Duration),
+
+    #[error("Передача не найдена: {0}")]
+    TransferNotFound(u64),
+
+    #[error("Недействительный CSRF-токен")]
+    InvalidCsrf,
     // #[error("Внутренняя ошибка сервера: {0}")]
     // Internal(String),
 }
 
+impl AppError {
+    /// HTTP-статус, соответствующий ошибке.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Fs(e) => match e {
+                FsError::PathNotFound { .. } => StatusCode::NOT_FOUND,
+                FsError::PermissionDenied { .. } => StatusCode::FORBIDDEN,
+                FsError::InvalidFileName { .. } => StatusCode::BAD_REQUEST,
+                FsError::NotConnected => StatusCode::CONFLICT,
+                FsError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+                FsError::Ftp(_) => StatusCode::BAD_GATEWAY,
+                FsError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                FsError::Io(_) | FsError::Netrc(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                FsError::QuotaExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+                FsError::FileBusy { .. } => StatusCode::LOCKED,
+                FsError::NotLoggedIn { .. } => StatusCode::UNAUTHORIZED,
+                FsError::DataConnection { .. } | FsError::SizeMismatch { .. } => {
+                    StatusCode::BAD_GATEWAY
+                }
+                FsError::NotSupported { .. } => StatusCode::NOT_IMPLEMENTED,
+                FsError::ServiceClosing(_) | FsError::ConnectionLost => {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            },
+            Self::NotConnected => StatusCode::CONFLICT,
+            Self::Profile(e) => match e {
+                ProfileError::NotFound(_) => StatusCode::NOT_FOUND,
+                ProfileError::InvalidName(_) => StatusCode::BAD_REQUEST,
+                ProfileError::NoMasterKey
+                | ProfileError::VaultLocked
+                | ProfileError::AlreadyExists(_) => StatusCode::CONFLICT,
+                ProfileError::Io(_) | ProfileError::Json(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Target(TargetError::Blocked(_)) => StatusCode::FORBIDDEN,
+            Self::Target(TargetError::Resolve { .. }) => StatusCode::BAD_GATEWAY,
+            Self::Throttled(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::TransferNotFound(_) => StatusCode::NOT_FOUND,
+            Self::InvalidCsrf => StatusCode::FORBIDDEN,
+        }
+    }
+
+    /// Машиночитаемый код ошибки для JSON API.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Fs(e) => match e {
+                FsError::Io(_) => "io",
+                FsError::Ftp(_) => "ftp",
+                FsError::Timeout => "timeout",
+                FsError::NotConnected => "not_connected",
+                FsError::PathNotFound { .. } => "path_not_found",
+                FsError::PermissionDenied { .. } => "permission_denied",
+                FsError::InvalidFileName { .. } => "invalid_file_name",
+                FsError::Netrc(_) => "netrc",
+                FsError::QuotaExceeded { .. } => "quota_exceeded",
+                FsError::FileBusy { .. } => "file_busy",
+                FsError::NotLoggedIn { .. } => "not_logged_in",
+                FsError::DataConnection { .. } => "data_connection",
+                FsError::NotSupported { .. } => "not_supported",
+                FsError::ServiceClosing(_) => "service_closing",
+                FsError::ConnectionLost => "connection_lost",
+                FsError::SizeMismatch { .. } => "size_mismatch",
+            },
+            Self::NotConnected => "not_connected",
+            Self::Profile(ProfileError::NotFound(_)) => "profile_not_found",
+            Self::Profile(ProfileError::AlreadyExists(_)) => "profile_exists",
+            Self::Profile(_) => "profile",
+            Self::BadRequest(_) => "bad_request",
+            Self::Target(TargetError::Blocked(_)) => "target_blocked",
+            Self::Target(TargetError::Resolve { .. }) => "target_unresolved",
+            Self::Throttled(_) => "throttled",
+            Self::TransferNotFound(_) => "transfer_not_found",
+            Self::InvalidCsrf => "invalid_csrf",
+        }
+    }
+
+    /// Код ответа FTP-сервера, если ошибку вернул он.
+    pub fn reply_code(&self) -> Option<u32> {
+        match self {
+            Self::Fs(e) => e.reply_code(),
+            _ => None,
+        }
+    }
+
+    /// Путь или имя файла, к которому относится ошибка.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::Fs(
+                FsError::PathNotFound { path, .. }
+                | FsError::PermissionDenied { path, .. }
+                | FsError::InvalidFileName { name: path, .. }
+                | FsError::SizeMismatch { path, .. },
+            ) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Понятное пользователю сообщение для ошибок `ftp_fs`.
+pub(crate) fn fs_message(e: &FsError) -> String {
+    match e {
+        FsError::PathNotFound { path, .. } => format!("Файл или директория не найдены: {}", path),
+        FsError::PermissionDenied { path, .. } => format!("Нет доступа: {}", path),
+        FsError::InvalidFileName { name, .. } => format!("Недопустимое имя файла: {:?}", name),
+        FsError::QuotaExceeded { path, .. } => format!("На сервере не хватает места для {}", path),
+        FsError::FileBusy { path, .. } => format!("Файл занят, повторите позже: {}", path),
+        FsError::NotLoggedIn { .. } => "Сервер не принял имя пользователя или пароль".to_string(),
+        FsError::DataConnection { code, .. } => format!(
+            "Не удалось передать данные ({}); попробуйте другой режим канала данных",
+            code
+        ),
+        FsError::NotSupported { message, .. } => format!("Сервер не поддерживает команду: {}", message),
+        FsError::ServiceClosing(_) | FsError::ConnectionLost => {
+            "Сервер закрыл соединение, подключитесь заново".to_string()
+        }
+        FsError::SizeMismatch {
+            path,
+            expected,
+            actual,
+        } => format!(
+            "Файл {} передан не полностью: на сервере {} байт, передано {}",
+            path, expected, actual
+        ),
+        FsError::Timeout => "Сервер не ответил вовремя".to_string(),
+        FsError::NotConnected => "Нет активного соединения с сервером".to_string(),
+        e => format!("Ошибка файловой системы: {}", e),
+    }
+}
+
+/// Тело ошибки JSON API: `{"error": {"code": ..., "message": ...}}`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Машиночитаемый код: `not_connected`, `path_not_found`, `ftp`, ...
+    pub code: &'static str,
+    /// Сообщение для человека.
+    pub message: String,
+    /// Код ответа FTP-сервера.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<u32>,
+    /// Путь или имя файла.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl From<&AppError> for ErrorBody {
+    fn from(e: &AppError) -> Self {
+        Self {
+            code: e.code(),
+            message: e.to_string(),
+            reply_code: e.reply_code(),
+            path: e.path().map(str::to_string),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let msg = match self {
-            Self::NotConnected => "<li>Нет активного соединения</li>".to_string(),
-            _ => format!("<li>Ошибка: {}</li>", self),
-        };
-        Html(msg).into_response()
+        let status = self.status();
+        if status.is_server_error() {
+            log::error!("{}", self);
+        }
+        let html = NotificationTemplate {
+            message: self.to_string(),
+            reply_code: self.reply_code(),
+            path: self.path().map(str::to_string),
+        }
+        .render()
+        .unwrap();
+        (
+            status,
+            [
+                ("HX-Retarget", "#notifications"),
+                ("HX-Reswap", "innerHTML"),
+            ],
+            Html(html),
+        )
+            .into_response()
     }
 }
+
+/// Ошибка запуска сервера — печатается вместо паники.
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error("Ошибка конфигурации: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("Некорректный корень локальной ФС {path}: {source}")]
+    LocalRoot { path: PathBuf, source: FsError },
+
+    #[error("Ключ {0:?} в [server.user_roots] должен иметь вид логин@хост")]
+    UserRootKey(String),
+
+    #[error("Директория статики не найдена: {0}")]
+    AssetsDir(PathBuf),
+
+    #[error("Не удалось открыть профили: {0}")]
+    Profiles(#[from] ProfileError),
+
+    #[error("Не удалось загрузить TLS-сертификат: {0}")]
+    Tls(std::io::Error),
+
+    #[error("Не удалось занять адрес {addr}: {source}")]
+    Bind {
+        addr: SocketAddr,
+        source: std::io::Error,
+    },
+
+    #[error("Ошибка веб-сервера: {0}")]
+    Serve(std::io::Error),
+}
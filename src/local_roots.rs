@@ -0,0 +1,117 @@
+//! Корни локальной ФС: общий и отдельные для пользователей FTP.
+//!
+//! Свой корень выдаётся по паре «логин и хост FTP-сервера» из
+//! `[server.user_roots]`: тот же логин на другом сервере — другой
+//! пользователь. При подключении [`crate::routes::switch_local_root`]
+//! открывает этот корень только в браузерной сессии, которая подключилась;
+//! остальные сессии и JSON API работают в общем корне.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use ftp_fs::{FsResult, LocalFs};
+
+use crate::error::StartupError;
+
+/// Проверенные при старте корни локальной ФС.
+pub struct LocalRoots {
+    default: PathBuf,
+    /// Корни по логину и хосту (хост в нижнем регистре).
+    users: HashMap<(String, String), PathBuf>,
+}
+
+impl LocalRoots {
+    /// Проверить корни из конфигурации: ключи вида `логин@хост`, каждый
+    /// корень должен существовать и быть директорией. Пути приводятся к
+    /// каноническому виду.
+    pub fn new(default: PathBuf, users: &HashMap<String, PathBuf>) -> Result<Self, StartupError> {
+        let canonical = |path: &PathBuf| {
+            LocalFs::new(path.clone())
+                .map(|fs| fs.root().to_path_buf())
+                .map_err(|source| StartupError::LocalRoot {
+                    path: path.clone(),
+                    source,
+                })
+        };
+        Ok(Self {
+            default: canonical(&default)?,
+            users: users
+                .iter()
+                .map(|(key, root)| {
+                    // Логин сам может содержать «@», хост — нет
+                    let (user, host) = key
+                        .rsplit_once('@')
+                        .filter(|(user, host)| !user.is_empty() && !host.is_empty())
+                        .ok_or_else(|| StartupError::UserRootKey(key.clone()))?;
+                    Ok(((user.to_string(), normalize_host(host)), canonical(root)?))
+                })
+                .collect::<Result<_, StartupError>>()?,
+        })
+    }
+
+    /// Свой корень пользователя `username` на сервере `host`, если он настроен.
+    pub fn user_root(&self, host: &str, username: &str) -> Option<&Path> {
+        self.users
+            .get(&(username.to_string(), normalize_host(host)))
+            .map(PathBuf::as_path)
+    }
+
+    /// [`LocalFs`] в общем корне.
+    pub fn open_default(&self) -> FsResult<LocalFs> {
+        LocalFs::new(self.default.clone())
+    }
+}
+
+/// Имя хоста для сравнения: без учёта регистра и точки в конце.
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots(dir: &Path, keys: &[&str]) -> Result<LocalRoots, StartupError> {
+        let users = keys
+            .iter()
+            .map(|key| (key.to_string(), dir.to_path_buf()))
+            .collect();
+        LocalRoots::new(dir.to_path_buf(), &users)
+    }
+
+    #[test]
+    fn user_root_needs_both_login_and_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = roots(dir.path(), &["alice@ftp.example.com"]).unwrap();
+
+        assert!(roots.user_root("ftp.example.com", "alice").is_some());
+        assert!(roots.user_root("FTP.Example.com.", "alice").is_some());
+        assert!(roots.user_root("attacker.example.net", "alice").is_none());
+        assert!(roots.user_root("ftp.example.com", "bob").is_none());
+    }
+
+    #[test]
+    fn login_may_contain_at_sign() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = roots(dir.path(), &["alice@corp.example@ftp.example.com"]).unwrap();
+
+        assert!(
+            roots
+                .user_root("ftp.example.com", "alice@corp.example")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn key_without_host_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        for key in ["alice", "alice@", "@ftp.example.com"] {
+            assert!(
+                matches!(roots(dir.path(), &[key]), Err(StartupError::UserRootKey(_))),
+                "{key}"
+            );
+        }
+    }
+}
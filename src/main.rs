@@ -1,25 +1,94 @@
 use axum::{
-    Router,
+    Router, middleware,
     routing::{get, post},
 };
-use ftp_fs::LocalFs;
-use std::sync::Arc;
+use clap::Parser;
+use std::{net::SocketAddr, path::PathBuf, process::ExitCode, sync::Arc};
 use tokio::sync::Mutex;
 use tower_http::{compression::CompressionLayer, services::ServeDir};
 
+use crate::error::StartupError;
+
+mod api;
+mod bandwidth;
+mod config;
+mod conflicts;
+mod csrf;
 mod error;
+mod local_roots;
+mod profiles;
+mod queue;
 mod routes;
+mod session;
 mod state;
+mod status;
+mod target_policy;
 mod templates;
+mod throttle;
+mod tls;
+mod transfers;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let cli = config::Cli::parse();
+    match run(&cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: &config::Cli) -> Result<(), StartupError> {
+    let config = Arc::new(config::Config::load(cli)?);
+
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .parse_default_env()
+        .init();
+
+    let local_root = match &config.server.local_root {
+        Some(root) => root.clone(),
+        None => std::env::current_dir().map_err(|e| StartupError::LocalRoot {
+            path: PathBuf::from("."),
+            source: e.into(),
+        })?,
+    };
+    let local_roots = local_roots::LocalRoots::new(local_root.clone(), &config.server.user_roots)?;
+    let local_fs = local_roots
+        .open_default()
+        .map_err(|source| StartupError::LocalRoot {
+            path: local_root,
+            source,
+        })?;
+
+    if !config.server.assets_dir.is_dir() {
+        return Err(StartupError::AssetsDir(config.server.assets_dir.clone()));
+    }
+
+    // Под HTTPS cookie сессии никогда не уходит по открытому каналу
+    let mut session_settings = config.session.clone();
+    session_settings.secure_cookie |= config.tls.is_some();
+
     let state = state::AppState {
         ftp: Arc::new(Mutex::new(None)),
-        connection_error: Arc::new(Mutex::new(None)),
-        local_fs: Arc::new(Mutex::new(LocalFs::new(std::env::current_dir().unwrap()))),
-        transfer_status: Arc::new(Mutex::new(None)),
+        local_fs: Arc::new(Mutex::new(local_fs)),
+        local_roots: Arc::new(local_roots),
+        status: Arc::new(status::StatusChannel::new()),
+        sessions: session::SessionStore::new(session_settings),
+        profiles: Arc::new(Mutex::new(profiles::ProfileStore::open(&config.profiles)?)),
+        imports: Arc::new(profiles::filezilla::PendingImports::new()),
+        throttle: Arc::new(throttle::LoginThrottle::new(config.throttle.clone())),
+        queue: Arc::new(queue::TransferQueue::new()),
+        transfers: Arc::new(transfers::TransferJobs::new()),
+        bandwidth: Arc::new(bandwidth::Bandwidth::new(
+            config.transfers.bandwidth.clone(),
+        )),
+        conflicts: Arc::new(conflicts::ConflictPrompts::new()),
+        config: config.clone(),
     };
+    bandwidth::spawn_scheduler(state.bandwidth.clone(), state.status.clone());
 
     let app = Router::new()
         .route("/", get(routes::index))
@@ -28,18 +97,73 @@ async fn main() {
         .route("/list", get(routes::list_handler))
         .route("/change_directory", post(routes::change_directory_handler))
         .route("/local_list", get(routes::list_local))
+        .route("/server_info", get(routes::server_info))
         .route(
             "/local_change_directory",
             post(routes::change_local_directory),
         )
         .route("/upload", post(routes::upload_handler))
         .route("/download", post(routes::download_handler))
+        .route("/queue/cancel", post(routes::cancel_queued))
+        .route("/queue/retry", post(routes::retry_queued))
+        .route("/queue/clear", post(routes::clear_queue))
+        .route("/bandwidth", post(routes::set_bandwidth))
+        .route("/conflicts/resolve", post(routes::resolve_conflicts))
         .route("/events", get(routes::events))
-        .nest_service("/assets", ServeDir::new("assets"))
+        .route(
+            "/profiles",
+            get(routes::profiles_page).post(routes::save_profile),
+        )
+        .route("/profiles/picker", get(routes::profile_picker))
+        .route("/profiles/edit", get(routes::edit_profile))
+        .route("/profiles/delete", post(routes::delete_profile))
+        .route("/profiles/connect", post(routes::connect_profile))
+        .route("/profiles/import", post(routes::import_preview))
+        .route("/profiles/import/apply", post(routes::import_apply))
+        .nest("/api/v1", api::router())
+        .nest_service("/assets", ServeDir::new(&config.server.assets_dir))
+        .layer(middleware::from_fn_with_state(state.clone(), csrf::protect))
         .layer(CompressionLayer::new())
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    println!("Сервер запущен на http://0.0.0.0:3000");
-    axum::serve(listener, app).await.unwrap();
+    let addr = SocketAddr::new(config.server.bind, config.server.port);
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    let Some(tls) = &config.tls else {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|source| StartupError::Bind { addr, source })?;
+        log::info!("Сервер запущен на http://{}", addr);
+        return axum::serve(listener, make_service)
+            .await
+            .map_err(StartupError::Serve);
+    };
+
+    let rustls = tls::load(tls).await.map_err(StartupError::Tls)?;
+    tls::spawn_reloader(rustls.clone(), tls.clone());
+
+    if let Some(port) = tls.redirect_http_port {
+        let redirect_addr = SocketAddr::new(config.server.bind, port);
+        let listener = tokio::net::TcpListener::bind(redirect_addr)
+            .await
+            .map_err(|source| StartupError::Bind {
+                addr: redirect_addr,
+                source,
+            })?;
+        log::info!("Перенаправление http://{} -> https", redirect_addr);
+        let redirect = tls::redirect_app(config.server.port);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, redirect).await {
+                log::error!("HTTP-перенаправление остановлено: {}", e);
+            }
+        });
+    }
+
+    let listener =
+        std::net::TcpListener::bind(addr).map_err(|source| StartupError::Bind { addr, source })?;
+    log::info!("Сервер запущен на https://{}", addr);
+    axum_server::from_tcp_rustls(listener, rustls)
+        .serve(make_service)
+        .await
+        .map_err(StartupError::Serve)
 }
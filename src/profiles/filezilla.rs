@@ -0,0 +1,443 @@
+//! Импорт профилей из `sitemanager.xml` FileZilla.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use ftp_fs::{FtpConnectParams, FtpMode};
+use roxmltree::{Document, Node};
+
+use super::{Profile, ProtocolOptions};
+use crate::session::random_token;
+
+/// Сколько разобранный файл ждёт подтверждения импорта.
+const PENDING_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Запись, которую можно импортировать как профиль.
+pub struct ImportEntry {
+    pub profile: Profile,
+    /// Расшифрованный пароль, если его удалось перенести.
+    pub password: Option<String>,
+    /// Что было перенесено не полностью.
+    pub notes: Vec<String>,
+}
+
+/// Запись, которую импортировать нельзя.
+#[derive(Clone)]
+pub struct SkippedEntry {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Результат разбора `sitemanager.xml`.
+#[derive(Default)]
+pub struct ImportPlan {
+    pub entries: Vec<ImportEntry>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// Разобранные файлы, ожидающие подтверждения импорта.
+///
+/// Предпросмотр оставляет [`ImportPlan`] здесь и отдаёт странице только
+/// случайный токен: файл с паролями не возвращается в браузер.
+pub struct PendingImports {
+    plans: Mutex<HashMap<String, (Instant, ImportPlan)>>,
+}
+
+impl PendingImports {
+    pub fn new() -> Self {
+        Self {
+            plans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Отложить план до подтверждения и вернуть его токен.
+    pub fn insert(&self, plan: ImportPlan) -> String {
+        let mut plans = self.plans.lock().unwrap();
+        plans.retain(|_, (created, _)| created.elapsed() < PENDING_TTL);
+        let token = random_token();
+        plans.insert(token.clone(), (Instant::now(), plan));
+        token
+    }
+
+    /// Забрать план по токену — подтвердить импорт можно только один раз.
+    pub fn take(&self, token: &str) -> Option<ImportPlan> {
+        let (created, plan) = self.plans.lock().unwrap().remove(token)?;
+        (created.elapsed() < PENDING_TTL).then_some(plan)
+    }
+}
+
+/// Разобрать `sitemanager.xml`.
+///
+/// Папки превращаются в префикс имени профиля (`Папка/Подпапка/Сервер`).
+/// Ошибкой считается только невалидный XML; записи, которые нельзя
+/// перенести, попадают в [`ImportPlan::skipped`] с причиной.
+pub fn parse(xml: &str) -> Result<ImportPlan, roxmltree::Error> {
+    let doc = Document::parse(xml)?;
+    let mut plan = ImportPlan::default();
+
+    if let Some(servers) = doc
+        .root_element()
+        .children()
+        .find(|n| n.has_tag_name("Servers"))
+    {
+        walk(servers, "", &mut plan);
+    }
+
+    // Одинаковые имена внутри файла получают суффикс
+    let mut seen = std::collections::HashSet::new();
+    for entry in &mut plan.entries {
+        let base = entry.profile.name.clone();
+        let mut n = 2;
+        while !seen.insert(entry.profile.name.clone()) {
+            entry.profile.name = format!("{} ({})", base, n);
+            n += 1;
+        }
+    }
+
+    Ok(plan)
+}
+
+fn walk(node: Node, prefix: &str, plan: &mut ImportPlan) {
+    for child in node.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "Folder" => {
+                let name = child
+                    .children()
+                    .find(Node::is_text)
+                    .and_then(|t| t.text())
+                    .map(str::trim)
+                    .unwrap_or_default();
+                walk(child, &join_name(prefix, name), plan);
+            }
+            "Server" => match map_server(child, prefix) {
+                Ok(entry) => plan.entries.push(entry),
+                Err(skipped) => plan.skipped.push(skipped),
+            },
+            _ => {}
+        }
+    }
+}
+
+fn map_server(server: Node, prefix: &str) -> Result<ImportEntry, SkippedEntry> {
+    let field = |tag: &str| {
+        server
+            .children()
+            .find(|n| n.has_tag_name(tag))
+            .and_then(|n| n.text())
+            .map(str::trim)
+            .unwrap_or_default()
+    };
+
+    let host = field("Host");
+    let title = match field("Name") {
+        "" => host,
+        name => name,
+    };
+    let name = join_name(prefix, title);
+    let skip = |reason: String| SkippedEntry {
+        name: name.clone(),
+        reason,
+    };
+
+    if host.is_empty() {
+        return Err(skip("не указан адрес сервера".to_string()));
+    }
+
+    let mut notes = Vec::new();
+
+    // 0 — FTP с TLS при наличии, 6 — обычный FTP; остальное клиент не умеет
+    match field("Protocol") {
+        "" | "6" => {}
+        "0" => notes.push("TLS не поддерживается, подключение будет без шифрования".to_string()),
+        "1" => return Err(skip("SFTP не поддерживается".to_string())),
+        "3" | "4" => {
+            return Err(skip(
+                "FTPS (обязательный TLS) не поддерживается".to_string(),
+            ));
+        }
+        other => return Err(skip(format!("протокол {} не поддерживается", other))),
+    }
+
+    let port = match field("Port") {
+        "" => 21,
+        p => p
+            .parse()
+            .map_err(|_| skip(format!("некорректный порт {:?}", p)))?,
+    };
+
+    // Logontype: 0 — анонимный, 1 — обычный, 2/3 — пароль спрашивается при входе
+    let (username, password) = match field("Logontype") {
+        "0" => ("anonymous".to_string(), Some("anonymous@".to_string())),
+        "2" | "3" => {
+            notes.push("пароль запрашивается при входе и не хранится".to_string());
+            (field("User").to_string(), None)
+        }
+        "" | "1" => (
+            field("User").to_string(),
+            decode_password(server, &mut notes),
+        ),
+        other => return Err(skip(format!("тип входа {} не поддерживается", other))),
+    };
+
+    let mode = match field("PasvMode") {
+        "MODE_ACTIVE" => FtpMode::Active,
+        _ => FtpMode::Passive,
+    };
+
+    let remote_dir = match field("RemoteDir") {
+        "" => String::new(),
+        raw => parse_remote_dir(raw).unwrap_or_else(|| {
+            notes.push(format!(
+                "не удалось разобрать удалённую директорию {:?}",
+                raw
+            ));
+            String::new()
+        }),
+    };
+
+    if !field("LocalDir").is_empty() {
+        notes
+            .push("локальная директория не перенесена: пути задаются от корня сервера".to_string());
+    }
+
+    Ok(ImportEntry {
+        profile: Profile {
+            name,
+            params: FtpConnectParams::new(host, port, username, "").into(),
+            options: ProtocolOptions { mode },
+            remote_dir,
+            local_dir: String::new(),
+        },
+        password,
+        notes,
+    })
+}
+
+fn decode_password(server: Node, notes: &mut Vec<String>) -> Option<String> {
+    let pass = server.children().find(|n| n.has_tag_name("Pass"))?;
+    let text = pass.text().unwrap_or_default().trim();
+    match pass.attribute("encoding") {
+        Some("base64") => match BASE64
+            .decode(text)
+            .ok()
+            .and_then(|b| String::from_utf8(b).ok())
+        {
+            Some(password) => Some(password),
+            None => {
+                notes.push("пароль повреждён и не перенесён".to_string());
+                None
+            }
+        },
+        Some("crypt") => {
+            notes.push("пароль защищён мастер-паролем FileZilla и не перенесён".to_string());
+            None
+        }
+        // Старые версии FileZilla хранили пароль открытым текстом
+        None => Some(text.to_string()),
+        Some(other) => {
+            notes.push(format!("неизвестная кодировка пароля {:?}", other));
+            None
+        }
+    }
+}
+
+/// Разобрать `RemoteDir` FileZilla: `"1 0 4 home 4 user"` → `/home/user`.
+///
+/// Формат: тип сервера, длина префикса, затем сегменты вида «длина имя».
+/// Длина (в символах) нужна, потому что имя может содержать пробелы.
+fn parse_remote_dir(raw: &str) -> Option<String> {
+    let mut rest = raw;
+    let _server_type = take_number(&mut rest)?;
+    let prefix_len = take_number(&mut rest)?;
+    if prefix_len > 0 {
+        take_chars(&mut rest, prefix_len)?;
+    }
+
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        let len = take_number(&mut rest)?;
+        segments.push(take_chars(&mut rest, len)?);
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+fn take_number(rest: &mut &str) -> Option<usize> {
+    let (number, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+    *rest = tail;
+    number.parse().ok()
+}
+
+fn take_chars<'a>(rest: &mut &'a str, len: usize) -> Option<&'a str> {
+    let end = match rest.char_indices().nth(len) {
+        Some((i, _)) => i,
+        None if rest.chars().count() == len => rest.len(),
+        None => return None,
+    };
+    let (chars, tail) = rest.split_at(end);
+    *rest = match tail {
+        "" => "",
+        tail => tail.strip_prefix(' ')?,
+    };
+    Some(chars)
+}
+
+fn join_name(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sitemanager(servers: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <FileZilla3><Servers>{servers}</Servers></FileZilla3>"
+        )
+    }
+
+    fn server(fields: &str) -> String {
+        format!("<Server><Host>ftp.example.com</Host>{fields}</Server>")
+    }
+
+    fn only_entry(xml: &str) -> ImportEntry {
+        let mut plan = parse(xml).unwrap();
+        assert!(plan.skipped.is_empty());
+        assert_eq!(plan.entries.len(), 1);
+        plan.entries.remove(0)
+    }
+
+    fn skip_reason(fields: &str) -> String {
+        let plan = parse(&sitemanager(&server(fields))).unwrap();
+        assert!(plan.entries.is_empty());
+        plan.skipped[0].reason.clone()
+    }
+
+    #[test]
+    fn remote_dir_is_decoded() {
+        assert_eq!(
+            parse_remote_dir("1 0 4 home 4 user").as_deref(),
+            Some("/home/user")
+        );
+        // Длина в символах: пробелы и кириллица внутри сегмента
+        assert_eq!(
+            parse_remote_dir("1 0 8 my files 5 отчёт").as_deref(),
+            Some("/my files/отчёт")
+        );
+        assert_eq!(parse_remote_dir("1 0").as_deref(), Some("/"));
+        assert_eq!(parse_remote_dir("1 0 10 short"), None);
+        assert_eq!(parse_remote_dir("x 0 4 home"), None);
+
+        let entry = only_entry(&sitemanager(&server(
+            "<RemoteDir>1 0 3 pub 8 incoming</RemoteDir>",
+        )));
+        assert_eq!(entry.profile.remote_dir, "/pub/incoming");
+
+        let entry = only_entry(&sitemanager(&server("<RemoteDir>1 0 9 pub</RemoteDir>")));
+        assert_eq!(entry.profile.remote_dir, "");
+        assert_eq!(entry.notes.len(), 1);
+    }
+
+    #[test]
+    fn logon_type_selects_credentials() {
+        let anonymous = only_entry(&sitemanager(&server(
+            "<Logontype>0</Logontype><User>ignored</User>",
+        )));
+        assert_eq!(anonymous.profile.params.username, "anonymous");
+        assert_eq!(anonymous.password.as_deref(), Some("anonymous@"));
+
+        let normal = only_entry(&sitemanager(&server(
+            "<Logontype>1</Logontype><User>bob</User>\
+             <Pass encoding=\"base64\">czNjcmV0</Pass>",
+        )));
+        assert_eq!(normal.profile.params.username, "bob");
+        assert_eq!(normal.password.as_deref(), Some("s3cret"));
+
+        let ask = only_entry(&sitemanager(&server(
+            "<Logontype>2</Logontype><User>bob</User>\
+             <Pass encoding=\"base64\">czNjcmV0</Pass>",
+        )));
+        assert_eq!(ask.profile.params.username, "bob");
+        assert_eq!(ask.password, None);
+        assert!(!ask.notes.is_empty());
+
+        let crypt = only_entry(&sitemanager(&server(
+            "<Logontype>1</Logontype><User>bob</User><Pass encoding=\"crypt\">xx</Pass>",
+        )));
+        assert_eq!(crypt.password, None);
+
+        assert_eq!(
+            skip_reason("<Logontype>5</Logontype>"),
+            "тип входа 5 не поддерживается"
+        );
+    }
+
+    #[test]
+    fn protocol_is_mapped() {
+        for plain in ["", "<Protocol>6</Protocol>"] {
+            let entry = only_entry(&sitemanager(&server(plain)));
+            assert!(entry.notes.is_empty());
+        }
+        let explicit_tls = only_entry(&sitemanager(&server("<Protocol>0</Protocol>")));
+        assert!(explicit_tls.notes[0].contains("TLS"));
+
+        assert_eq!(
+            skip_reason("<Protocol>1</Protocol>"),
+            "SFTP не поддерживается"
+        );
+        assert!(skip_reason("<Protocol>4</Protocol>").contains("FTPS"));
+        assert_eq!(
+            skip_reason("<Protocol>13</Protocol>"),
+            "протокол 13 не поддерживается"
+        );
+    }
+
+    #[test]
+    fn folders_ports_and_modes_are_mapped() {
+        let xml = sitemanager(
+            "<Folder>Работа<Server><Host>a.example.com</Host><Port>2121</Port>\
+             <PasvMode>MODE_ACTIVE</PasvMode><Name>Сайт</Name></Server></Folder>\
+             <Server><Host>b.example.com</Host></Server>\
+             <Server><Host>b.example.com</Host></Server>\
+             <Server><Name>пустой</Name></Server>\
+             <Server><Host>c.example.com</Host><Port>port</Port></Server>",
+        );
+        let plan = parse(&xml).unwrap();
+        let names: Vec<_> = plan.entries.iter().map(|e| &e.profile.name).collect();
+        assert_eq!(names, ["Работа/Сайт", "b.example.com", "b.example.com (2)"]);
+
+        let site = &plan.entries[0].profile;
+        assert_eq!(site.params.port, 2121);
+        assert_eq!(site.options.mode, FtpMode::Active);
+        assert_eq!(plan.entries[1].profile.params.port, 21);
+
+        let skipped: Vec<_> = plan.skipped.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(skipped, ["пустой", "c.example.com"]);
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        assert!(parse("<FileZilla3><Servers>").is_err());
+        assert!(parse("not xml").is_err());
+
+        let empty = parse("<FileZilla3/>").unwrap();
+        assert!(empty.entries.is_empty() && empty.skipped.is_empty());
+    }
+
+    #[test]
+    fn pending_import_is_taken_once() {
+        let pending = PendingImports::new();
+        let token = pending.insert(parse(&sitemanager(&server(""))).unwrap());
+        assert!(pending.take("unknown").is_none());
+        assert_eq!(pending.take(&token).unwrap().entries.len(), 1);
+        assert!(pending.take(&token).is_none());
+    }
+}
@@ -0,0 +1,416 @@
+//! Именованные профили подключения и зашифрованное хранилище их паролей.
+
+pub mod filezilla;
+pub mod vault;
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use ftp_fs::{FtpConnectParams, FtpMode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use self::vault::Vault;
+
+/// Переменная окружения с мастер-ключом хранилища паролей.
+pub const MASTER_KEY_ENV: &str = "WEB_FTP_VAULT_KEY";
+
+/// Ошибка работы с профилями.
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("Ошибка ввода-вывода: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Повреждённый файл профилей: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Хранилище паролей не открывается: неверный мастер-ключ или файл повреждён")]
+    VaultLocked,
+
+    #[error("Мастер-ключ хранилища не задан, пароль сохранить нельзя")]
+    NoMasterKey,
+
+    #[error("Профиль не найден: {0}")]
+    NotFound(String),
+
+    #[error("Недопустимое имя профиля: {0:?}")]
+    InvalidName(String),
+
+    #[error("Профиль с именем {0:?} уже существует")]
+    AlreadyExists(String),
+}
+
+/// Настройки хранения профилей (секция `[profiles]` конфигурации).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileSettings {
+    /// JSON-файл с профилями (без паролей).
+    pub file: PathBuf,
+    /// Зашифрованный файл с паролями.
+    pub vault: PathBuf,
+    /// Файл с мастер-ключом. Если не задан — берётся из `WEB_FTP_VAULT_KEY`.
+    pub master_key_file: Option<PathBuf>,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("profiles.json"),
+            vault: PathBuf::from("vault.json"),
+            master_key_file: None,
+        }
+    }
+}
+
+/// Параметры протокола, которые не входят в [`FtpConnectParams`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtocolOptions {
+    /// Режим канала данных.
+    pub mode: FtpMode,
+}
+
+/// Параметры подключения в том виде, в каком они хранятся в файле профилей.
+///
+/// Пароля здесь нет: он хранится отдельно, в [`Vault`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedParams {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Таймаут подключения в секундах.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    FtpConnectParams::new("", 21, "", "").timeout_secs
+}
+
+impl SavedParams {
+    /// Параметры для [`ftp_fs::FtpFs::connect`] с паролем `password`.
+    pub fn with_password(&self, password: impl Into<String>) -> FtpConnectParams {
+        let mut params = FtpConnectParams::new(
+            self.host.clone(),
+            self.port,
+            self.username.clone(),
+            password,
+        );
+        params.timeout_secs = self.timeout_secs;
+        params
+    }
+}
+
+impl From<FtpConnectParams> for SavedParams {
+    /// Пароль отбрасывается.
+    fn from(params: FtpConnectParams) -> Self {
+        Self {
+            host: params.host,
+            port: params.port,
+            username: params.username,
+            timeout_secs: params.timeout_secs,
+        }
+    }
+}
+
+/// Именованный профиль подключения.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub params: SavedParams,
+    #[serde(default)]
+    pub options: ProtocolOptions,
+    /// Начальная директория на FTP-сервере (пусто — домашняя).
+    #[serde(default)]
+    pub remote_dir: String,
+    /// Начальная локальная директория относительно корня `LocalFs`.
+    #[serde(default)]
+    pub local_dir: String,
+}
+
+/// Хранилище профилей: JSON-файл с профилями плюс [`Vault`] с паролями.
+pub struct ProfileStore {
+    file: PathBuf,
+    vault_file: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+    /// `None`, если мастер-ключ не задан — тогда пароли не сохраняются.
+    vault: Option<Vault>,
+}
+
+impl ProfileStore {
+    /// Загрузить профили и открыть хранилище паролей.
+    pub fn open(settings: &ProfileSettings) -> Result<Self, ProfileError> {
+        let master_key = match &settings.master_key_file {
+            Some(path) => Some(std::fs::read_to_string(path)?.trim().to_string()),
+            None => std::env::var(MASTER_KEY_ENV).ok(),
+        }
+        .filter(|key| !key.is_empty());
+
+        let vault = match master_key {
+            Some(key) => Some(Vault::open(&settings.vault, &key)?),
+            None => {
+                log::warn!(
+                    "Мастер-ключ не задан ({}), пароли профилей не сохраняются",
+                    MASTER_KEY_ENV
+                );
+                None
+            }
+        };
+
+        let profiles = if settings.file.exists() {
+            let list: Vec<Profile> =
+                serde_json::from_str(&std::fs::read_to_string(&settings.file)?)?;
+            list.into_iter().map(|p| (p.name.clone(), p)).collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            file: settings.file.clone(),
+            vault_file: settings.vault.clone(),
+            profiles,
+            vault,
+        })
+    }
+
+    /// Профили в алфавитном порядке.
+    pub fn list(&self) -> impl Iterator<Item = &Profile> {
+        self.profiles.values()
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile, ProfileError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ProfileError::NotFound(name.to_string()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.profiles.contains_key(name)
+    }
+
+    /// Можно ли сохранять пароли (задан ли мастер-ключ).
+    pub fn can_store_passwords(&self) -> bool {
+        self.vault.is_some()
+    }
+
+    pub fn has_password(&self, name: &str) -> bool {
+        self.vault.as_ref().is_some_and(|v| v.get(name).is_some())
+    }
+
+    /// Профиль и параметры подключения с подставленным из хранилища паролем.
+    pub fn connect_params(&self, name: &str) -> Result<(Profile, FtpConnectParams), ProfileError> {
+        let profile = self.get(name)?.clone();
+        let password = self.vault.as_ref().and_then(|v| v.get(name));
+        let params = profile.params.with_password(password.unwrap_or_default());
+        Ok((profile, params))
+    }
+
+    /// Создать или обновить профиль.
+    ///
+    /// `original` — имя редактируемого профиля: совпадает с новым при
+    /// обновлении, отличается при переименовании, `None` при создании.
+    /// Занять имя другого профиля нельзя — это [`ProfileError::AlreadyExists`].
+    /// Пароль `None` оставляет сохранённый пароль без изменений.
+    pub fn save(
+        &mut self,
+        original: Option<&str>,
+        profile: Profile,
+        password: Option<String>,
+    ) -> Result<(), ProfileError> {
+        self.put(original, profile, password)?;
+        self.persist()
+    }
+
+    /// Импортировать профили с одной записью на диск. Профиль с тем же
+    /// именем заменяется: импорт подтверждают после предпросмотра, где
+    /// замена показана.
+    ///
+    /// Возвращает результат по каждой записи в исходном порядке; ошибка
+    /// всей операции — только если не удалось записать файлы.
+    pub fn import(
+        &mut self,
+        entries: Vec<(Profile, Option<String>)>,
+    ) -> Result<Vec<Result<(), ProfileError>>, ProfileError> {
+        let results = entries
+            .into_iter()
+            .map(|(profile, password)| {
+                let name = profile.name.clone();
+                let original = self.contains(&name).then_some(name.as_str());
+                self.put(original, profile, password)
+            })
+            .collect();
+        self.persist()?;
+        Ok(results)
+    }
+
+    /// [`ProfileStore::save`] без записи на диск.
+    fn put(
+        &mut self,
+        original: Option<&str>,
+        profile: Profile,
+        password: Option<String>,
+    ) -> Result<(), ProfileError> {
+        validate_name(&profile.name)?;
+        if password.is_some() && self.vault.is_none() {
+            return Err(ProfileError::NoMasterKey);
+        }
+
+        let name = profile.name.clone();
+        if original != Some(name.as_str()) && self.contains(&name) {
+            return Err(ProfileError::AlreadyExists(name));
+        }
+        if let Some(old) = original.filter(|old| *old != name) {
+            self.get(old)?;
+            self.profiles.remove(old);
+            if let Some(vault) = self.vault.as_mut() {
+                if let Some(old_password) = vault.get(old).map(str::to_string) {
+                    vault.set(&name, old_password);
+                }
+                vault.remove(old);
+            }
+        }
+
+        self.profiles.insert(name.clone(), profile);
+        if let (Some(vault), Some(password)) = (self.vault.as_mut(), password) {
+            vault.set(&name, password);
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), ProfileError> {
+        self.profiles
+            .remove(name)
+            .ok_or_else(|| ProfileError::NotFound(name.to_string()))?;
+        if let Some(vault) = self.vault.as_mut() {
+            vault.remove(name);
+        }
+        self.persist()
+    }
+
+    fn persist(&self) -> Result<(), ProfileError> {
+        let list: Vec<&Profile> = self.profiles.values().collect();
+        write_atomically(&self.file, serde_json::to_string_pretty(&list)?.as_bytes())?;
+        if let Some(vault) = &self.vault {
+            vault.save(&self.vault_file)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), ProfileError> {
+    let valid = !name.trim().is_empty()
+        && name.trim() == name
+        && name.chars().count() <= 200
+        && !name.chars().any(char::is_control);
+    if valid {
+        Ok(())
+    } else {
+        Err(ProfileError::InvalidName(name.to_string()))
+    }
+}
+
+/// Записать файл через временный и переименование, чтобы не оставить
+/// половину файла при сбое. Файл доступен только владельцу.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), ProfileError> {
+    use std::io::Write;
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, host: &str) -> Profile {
+        Profile {
+            name: name.to_string(),
+            params: FtpConnectParams::new(host, 21, "user", "").into(),
+            options: ProtocolOptions::default(),
+            remote_dir: String::new(),
+            local_dir: String::new(),
+        }
+    }
+
+    fn open_store(dir: &Path) -> ProfileStore {
+        let key_file = dir.join("master.key");
+        std::fs::write(&key_file, "key\n").unwrap();
+        ProfileStore::open(&ProfileSettings {
+            file: dir.join("profiles.json"),
+            vault: dir.join("vault.json"),
+            master_key_file: Some(key_file),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn save_does_not_overwrite_another_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open_store(dir.path());
+        store
+            .save(None, profile("work", "a.example.com"), Some("a".into()))
+            .unwrap();
+        store
+            .save(None, profile("home", "b.example.com"), Some("b".into()))
+            .unwrap();
+
+        // Создание с занятым именем
+        assert!(matches!(
+            store.save(None, profile("work", "c.example.com"), Some("c".into())),
+            Err(ProfileError::AlreadyExists(_))
+        ));
+        // Переименование в занятое имя
+        assert!(matches!(
+            store.save(Some("home"), profile("work", "b.example.com"), None),
+            Err(ProfileError::AlreadyExists(_))
+        ));
+
+        // Обновление того же профиля разрешено и не трогает пароль
+        store
+            .save(Some("work"), profile("work", "d.example.com"), None)
+            .unwrap();
+
+        let store = open_store(dir.path());
+        let (_, work) = store.connect_params("work").unwrap();
+        assert_eq!(
+            (work.host.as_str(), work.password.as_str()),
+            ("d.example.com", "a")
+        );
+        let (_, home) = store.connect_params("home").unwrap();
+        assert_eq!(
+            (home.host.as_str(), home.password.as_str()),
+            ("b.example.com", "b")
+        );
+    }
+
+    #[test]
+    fn rename_moves_the_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = open_store(dir.path());
+        store
+            .save(None, profile("old", "a.example.com"), Some("a".into()))
+            .unwrap();
+        store
+            .save(Some("old"), profile("new", "a.example.com"), None)
+            .unwrap();
+
+        assert!(!store.contains("old") && !store.has_password("old"));
+        assert_eq!(store.connect_params("new").unwrap().1.password, "a");
+
+        let saved = std::fs::read_to_string(dir.path().join("profiles.json")).unwrap();
+        assert!(!saved.contains("password"), "{saved}");
+    }
+}
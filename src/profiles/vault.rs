@@ -0,0 +1,204 @@
+use std::{collections::HashMap, path::Path};
+
+use argon2::Argon2;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{ProfileError, write_atomically};
+
+const VAULT_VERSION: u32 = 1;
+
+/// Зашифрованный файл хранилища паролей.
+///
+/// Содержимое — JSON-словарь «имя профиля → пароль», зашифрованный
+/// XChaCha20-Poly1305 ключом, выведенным из мастер-ключа через Argon2id.
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Расшифрованное в памяти хранилище паролей профилей.
+pub struct Vault {
+    cipher: XChaCha20Poly1305,
+    salt: [u8; 16],
+    passwords: HashMap<String, String>,
+}
+
+impl Vault {
+    /// Открыть хранилище по пути `path`, создав пустое при отсутствии файла.
+    ///
+    /// Возвращает [`ProfileError::VaultLocked`], если мастер-ключ не подходит
+    /// или файл повреждён.
+    pub fn open(path: &Path, master_key: &str) -> Result<Self, ProfileError> {
+        if !path.exists() {
+            let salt: [u8; 16] = rand::random();
+            return Ok(Self {
+                cipher: derive_cipher(master_key, &salt)?,
+                salt,
+                passwords: HashMap::new(),
+            });
+        }
+
+        let text = std::fs::read_to_string(path)?;
+        let file: VaultFile = serde_json::from_str(&text)?;
+        if file.version != VAULT_VERSION {
+            return Err(ProfileError::VaultLocked);
+        }
+
+        let salt: [u8; 16] = decode_fixed(&file.salt)?;
+        let nonce: [u8; 24] = decode_fixed(&file.nonce)?;
+        let ciphertext = BASE64
+            .decode(&file.ciphertext)
+            .map_err(|_| ProfileError::VaultLocked)?;
+
+        let cipher = derive_cipher(master_key, &salt)?;
+        let plaintext = cipher
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &file.version.to_le_bytes(),
+                },
+            )
+            .map_err(|_| ProfileError::VaultLocked)?;
+        let passwords = serde_json::from_slice(&plaintext)?;
+
+        Ok(Self {
+            cipher,
+            salt,
+            passwords,
+        })
+    }
+
+    /// Пароль профиля, если он сохранён.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.passwords.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &str, password: String) {
+        self.passwords.insert(name.to_string(), password);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.passwords.remove(name);
+    }
+
+    /// Зашифровать и записать хранилище. Каждый раз используется новый nonce.
+    pub fn save(&self, path: &Path) -> Result<(), ProfileError> {
+        let plaintext = serde_json::to_vec(&self.passwords)?;
+        let nonce: [u8; 24] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &VAULT_VERSION.to_le_bytes(),
+                },
+            )
+            .map_err(|_| ProfileError::VaultLocked)?;
+
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            salt: BASE64.encode(self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        write_atomically(path, serde_json::to_string_pretty(&file)?.as_bytes())
+    }
+}
+
+fn derive_cipher(master_key: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, ProfileError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(master_key.as_bytes(), salt, &mut key)
+        .map_err(|_| ProfileError::VaultLocked)?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+fn decode_fixed<const N: usize>(value: &str) -> Result<[u8; N], ProfileError> {
+    BASE64
+        .decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ProfileError::VaultLocked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_survive_save_and_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+
+        let mut vault = Vault::open(&path, "мастер-ключ").unwrap();
+        vault.set("work", "s3cret".to_string());
+        vault.set("home", "пароль".to_string());
+        vault.remove("home");
+        vault.save(&path).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("s3cret"), "пароль записан открытым текстом");
+
+        let vault = Vault::open(&path, "мастер-ключ").unwrap();
+        assert_eq!(vault.get("work"), Some("s3cret"));
+        assert_eq!(vault.get("home"), None);
+    }
+
+    #[test]
+    fn wrong_master_key_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+        let mut vault = Vault::open(&path, "right").unwrap();
+        vault.set("work", "s3cret".to_string());
+        vault.save(&path).unwrap();
+
+        assert!(matches!(
+            Vault::open(&path, "wrong"),
+            Err(ProfileError::VaultLocked)
+        ));
+    }
+
+    #[test]
+    fn corrupt_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+        let mut vault = Vault::open(&path, "key").unwrap();
+        vault.set("work", "s3cret".to_string());
+        vault.save(&path).unwrap();
+
+        // Подменённый шифртекст не проходит проверку подлинности
+        let mut file: VaultFile =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let mut ciphertext = BASE64.decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = BASE64.encode(ciphertext);
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(
+            Vault::open(&path, "key"),
+            Err(ProfileError::VaultLocked)
+        ));
+
+        file.nonce = "short".to_string();
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(
+            Vault::open(&path, "key"),
+            Err(ProfileError::VaultLocked)
+        ));
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(
+            Vault::open(&path, "key"),
+            Err(ProfileError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,550 @@
+//! Очередь передач для панели на странице: каждый запрошенный файл с
+//! состоянием и прогрессом.
+//!
+//! [`crate::routes::handle_transfer`] ставит файлы в очередь и применяет к
+//! ним [`TransferEvent`] по ходу передачи, а SSE-поток `/events` отправляет
+//! панель клиентам при каждом изменении. Отмена выставляет флаг в
+//! [`CancelFlags`] пачки, и передача останавливается на ближайшем блоке данных.
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use ftp_fs::{CancelFlags, SkipReason, TransferEvent, TransferOptions};
+use tokio::sync::watch;
+
+use crate::{
+    conflicts::ConflictMode,
+    error::{AppError, fs_message},
+};
+
+/// Сколько завершённых файлов помнит очередь.
+const HISTORY_LIMIT: usize = 100;
+
+/// Как часто рассылать обновления прогресса: чаще браузер не успеет
+/// перерисовать полосы, а смена состояния файла рассылается сразу.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Состояние файла в очереди.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemState {
+    Pending,
+    Running,
+    Transferred,
+    Skipped(SkipReason),
+    Failed,
+    Cancelled,
+}
+
+impl ItemState {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, Self::Pending | Self::Running)
+    }
+}
+
+/// Директории, в которых передаётся пачка: повтор из очереди идёт из них,
+/// даже если пользователь успел перейти в другие.
+#[derive(Debug, Clone)]
+pub struct TransferDirs {
+    /// Локальная директория, абсолютный путь внутри корня [`ftp_fs::LocalFs`].
+    pub local: PathBuf,
+    /// Рабочая директория на сервере; `None` — текущая директория сессии.
+    pub remote: Option<String>,
+}
+
+/// Файл в очереди.
+#[derive(Debug, Clone)]
+pub struct QueueItem {
+    pub id: u64,
+    pub name: String,
+    pub is_upload: bool,
+    pub state: ItemState,
+    pub bytes: u64,
+    pub total: Option<u64>,
+    /// Когда началась передача файла.
+    pub started: Option<Instant>,
+    /// Длительность передачи — после её окончания.
+    pub elapsed: Option<Duration>,
+    /// Размер на сервере совпал с переданным.
+    pub verified: bool,
+    /// Текущая попытка (с единицы) и сколько их разрешено.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// Под каким именем файл сохраняется, если под запрошенным уже есть файл.
+    pub renamed: Option<String>,
+    /// Текст ошибки для [`ItemState::Failed`] или неудавшейся попытки,
+    /// после которой ждёт повтор.
+    pub error: Option<String>,
+    /// Откуда и куда передаётся файл.
+    pub dirs: TransferDirs,
+    /// Параметры и режим конфликтов, с которыми файл запрошен: повтор
+    /// передаёт его с тем же лимитом скорости и той же политикой.
+    pub options: TransferOptions,
+    pub conflict: ConflictMode,
+    /// Флаги отмены пачки и позиция файла в ней.
+    cancel: CancelFlags,
+    index: usize,
+}
+
+/// Очередь передач: ожидающие, выполняющиеся и последние
+/// [`HISTORY_LIMIT`] завершённых файлов.
+pub struct TransferQueue {
+    next_id: AtomicU64,
+    tx: watch::Sender<VecDeque<QueueItem>>,
+    /// Когда последний раз рассылался прогресс.
+    notified_at: Mutex<Instant>,
+}
+
+impl TransferQueue {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            tx: watch::Sender::new(VecDeque::new()),
+            notified_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<VecDeque<QueueItem>> {
+        self.tx.subscribe()
+    }
+
+    /// Поставить файлы в очередь; возвращает их идентификаторы в том же порядке.
+    ///
+    /// `cancel` — флаги отмены пачки: через них [`TransferQueue::cancel`]
+    /// останавливает передачу; `options` и `conflict` — с чем запрошена
+    /// пачка, число попыток берётся из их политики повторов.
+    pub fn enqueue(
+        &self,
+        names: &[String],
+        is_upload: bool,
+        cancel: &CancelFlags,
+        dirs: &TransferDirs,
+        options: &TransferOptions,
+        conflict: ConflictMode,
+    ) -> Vec<u64> {
+        let ids: Vec<u64> = names
+            .iter()
+            .map(|_| self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+            .collect();
+        self.tx.send_modify(|items| {
+            items.extend(
+                names
+                    .iter()
+                    .zip(&ids)
+                    .enumerate()
+                    .map(|(index, (name, &id))| QueueItem {
+                        id,
+                        name: name.clone(),
+                        is_upload,
+                        state: ItemState::Pending,
+                        bytes: 0,
+                        total: None,
+                        started: None,
+                        elapsed: None,
+                        verified: false,
+                        attempt: 1,
+                        max_attempts: options.retry.max_attempts,
+                        renamed: None,
+                        error: None,
+                        dirs: dirs.clone(),
+                        options: options.clone(),
+                        conflict,
+                        cancel: cancel.clone(),
+                        index,
+                    }),
+            );
+            let finished = items.iter().filter(|i| i.state.is_finished()).count();
+            for _ in HISTORY_LIMIT..finished {
+                if let Some(pos) = items.iter().position(|i| i.state.is_finished()) {
+                    items.remove(pos);
+                }
+            }
+        });
+        ids
+    }
+
+    /// Применить событие передачи к файлу `id`.
+    ///
+    /// Отменённый файл не возвращается к выполнению из-за запоздавших
+    /// `Progress` или `Retrying`; его итог определяет завершающее событие.
+    pub fn apply(&self, id: u64, event: &TransferEvent) {
+        self.tx.send_if_modified(|items| {
+            let Some(item) = items.iter_mut().find(|i| i.id == id) else {
+                return false;
+            };
+            match event {
+                TransferEvent::Started { .. }
+                | TransferEvent::Progress { .. }
+                | TransferEvent::Retrying { .. }
+                    if item.state == ItemState::Cancelled =>
+                {
+                    false
+                }
+                TransferEvent::Renamed { target, .. } => {
+                    // Следом придёт Started — тогда и разослать
+                    item.renamed = Some(target.clone());
+                    false
+                }
+                TransferEvent::Started { total, .. } => {
+                    item.state = ItemState::Running;
+                    item.total = *total;
+                    item.started = Some(Instant::now());
+                    self.mark_notified(true)
+                }
+                TransferEvent::Progress { bytes, total, .. } => {
+                    item.bytes = *bytes;
+                    item.total = *total;
+                    // Повтор пошёл: ошибка прошлой попытки больше не актуальна
+                    let resumed = item.error.take().is_some();
+                    self.mark_notified(resumed)
+                }
+                TransferEvent::Retrying { attempt, error, .. } => {
+                    item.attempt = *attempt;
+                    item.error = Some(fs_message(error));
+                    self.mark_notified(true)
+                }
+                TransferEvent::Verified { .. } => {
+                    item.verified = true;
+                    false
+                }
+                TransferEvent::Completed {
+                    bytes, duration, ..
+                } => {
+                    item.state = ItemState::Transferred;
+                    item.bytes = *bytes;
+                    item.elapsed = Some(*duration);
+                    true
+                }
+                TransferEvent::Skipped { reason, .. } => {
+                    item.state = match reason {
+                        SkipReason::Cancelled => ItemState::Cancelled,
+                        reason => ItemState::Skipped(*reason),
+                    };
+                    true
+                }
+                TransferEvent::Failed { error, .. } => {
+                    item.state = ItemState::Failed;
+                    item.error = Some(fs_message(error));
+                    true
+                }
+            }
+        });
+    }
+
+    /// Запомнить рабочую директорию на сервере, в которой передаются файлы
+    /// `ids`, — когда пачка получила соединение и она стала известна.
+    pub fn set_remote_dir(&self, ids: &[u64], dir: &str) {
+        self.tx.send_if_modified(|items| {
+            for item in items.iter_mut().filter(|i| ids.contains(&i.id)) {
+                item.dirs.remote.get_or_insert_with(|| dir.to_string());
+            }
+            false
+        });
+    }
+
+    /// Пачку не удалось начать: все её незавершённые файлы — с ошибкой `err`.
+    pub fn fail(&self, ids: &[u64], err: &AppError) {
+        self.tx.send_modify(|items| {
+            for item in items.iter_mut().filter(|i| ids.contains(&i.id)) {
+                if !item.state.is_finished() {
+                    item.state = ItemState::Failed;
+                    item.error = Some(err.to_string());
+                }
+            }
+        });
+    }
+
+    /// Отменить ожидающий или выполняющийся файл. Завершённые не меняются.
+    pub fn cancel(&self, id: u64) -> Result<(), AppError> {
+        let mut found = false;
+        self.tx.send_if_modified(|items| {
+            let Some(item) = items.iter_mut().find(|i| i.id == id) else {
+                return false;
+            };
+            found = true;
+            if item.state.is_finished() {
+                return false;
+            }
+            item.state = ItemState::Cancelled;
+            item.cancel.cancel(item.index);
+            true
+        });
+        if found {
+            Ok(())
+        } else {
+            Err(AppError::TransferNotFound(id))
+        }
+    }
+
+    /// Убрать неудавшийся или отменённый файл из очереди, чтобы передать
+    /// его заново.
+    pub fn take_for_retry(&self, id: u64) -> Result<QueueItem, AppError> {
+        let mut result = Err(AppError::TransferNotFound(id));
+        self.tx.send_if_modified(|items| {
+            let Some(pos) = items.iter().position(|i| i.id == id) else {
+                return false;
+            };
+            if !matches!(items[pos].state, ItemState::Failed | ItemState::Cancelled) {
+                result = Err(AppError::BadRequest(
+                    "Повторить можно только неудавшуюся или отменённую передачу".to_string(),
+                ));
+                return false;
+            }
+            result = Ok(items.remove(pos).expect("position is in range"));
+            true
+        });
+        result
+    }
+
+    /// Убрать из очереди завершённые файлы.
+    pub fn clear_finished(&self) {
+        self.tx.send_if_modified(|items| {
+            let before = items.len();
+            items.retain(|i| !i.state.is_finished());
+            items.len() != before
+        });
+    }
+
+    /// Разослать ли обновление: всегда при `force`, иначе не чаще
+    /// [`PROGRESS_INTERVAL`].
+    fn mark_notified(&self, force: bool) -> bool {
+        let mut notified_at = self.notified_at.lock().unwrap();
+        if force || notified_at.elapsed() >= PROGRESS_INTERVAL {
+            *notified_at = Instant::now();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for TransferQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ftp_fs::FsError;
+
+    use super::*;
+
+    fn enqueue(queue: &TransferQueue, count: usize, cancel: &CancelFlags) -> Vec<u64> {
+        let names: Vec<String> = (0..count).map(|i| format!("file{i}.txt")).collect();
+        let dirs = TransferDirs {
+            local: PathBuf::from("/data"),
+            remote: None,
+        };
+        queue.enqueue(
+            &names,
+            true,
+            cancel,
+            &dirs,
+            &TransferOptions::default(),
+            ConflictMode::default(),
+        )
+    }
+
+    fn item(queue: &TransferQueue, id: u64) -> QueueItem {
+        queue
+            .subscribe()
+            .borrow()
+            .iter()
+            .find(|i| i.id == id)
+            .cloned()
+            .expect("file is in the queue")
+    }
+
+    fn started(index: usize) -> TransferEvent {
+        TransferEvent::Started {
+            index,
+            name: format!("file{index}.txt"),
+            total: Some(100),
+        }
+    }
+
+    fn progress(index: usize, bytes: u64) -> TransferEvent {
+        TransferEvent::Progress {
+            index,
+            bytes,
+            total: Some(100),
+        }
+    }
+
+    fn finish(queue: &TransferQueue, id: u64, event: TransferEvent) {
+        queue.apply(id, &started(event.index()));
+        queue.apply(id, &event);
+    }
+
+    #[test]
+    fn cancelled_file_ignores_late_events() {
+        let queue = TransferQueue::new();
+        let cancel = CancelFlags::new();
+        let id = enqueue(&queue, 1, &cancel)[0];
+        queue.apply(id, &started(0));
+        queue.apply(id, &progress(0, 10));
+
+        queue.cancel(id).unwrap();
+        assert!(cancel.is_cancelled(0));
+        let mut rx = queue.subscribe();
+        rx.borrow_and_update();
+
+        // Передача ещё не дошла до флага и шлёт события
+        queue.apply(id, &progress(0, 50));
+        queue.apply(
+            id,
+            &TransferEvent::Retrying {
+                index: 0,
+                attempt: 2,
+                delay: Duration::from_secs(1),
+                error: FsError::Timeout,
+            },
+        );
+        assert!(!rx.has_changed().unwrap());
+        let cancelled = item(&queue, id);
+        assert_eq!(cancelled.state, ItemState::Cancelled);
+        assert_eq!(cancelled.bytes, 10);
+        assert_eq!(cancelled.attempt, 1);
+        assert!(cancelled.error.is_none());
+
+        // Завершённый файл отменить уже нельзя, но он есть в очереди
+        assert!(queue.cancel(id).is_ok());
+        assert!(matches!(
+            queue.cancel(id + 1),
+            Err(AppError::TransferNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn resumed_retry_clears_previous_error() {
+        let queue = TransferQueue::new();
+        let id = enqueue(&queue, 1, &CancelFlags::new())[0];
+        queue.apply(id, &started(0));
+        let mut rx = queue.subscribe();
+        rx.borrow_and_update();
+
+        queue.apply(
+            id,
+            &TransferEvent::Retrying {
+                index: 0,
+                attempt: 2,
+                delay: Duration::from_secs(1),
+                error: FsError::Timeout,
+            },
+        );
+        assert!(rx.has_changed().unwrap());
+        rx.borrow_and_update();
+        let waiting = item(&queue, id);
+        assert_eq!(waiting.attempt, 2);
+        assert!(waiting.error.is_some());
+
+        // Первый прогресс новой попытки рассылается сразу, следующий — нет
+        queue.apply(id, &progress(0, 10));
+        assert!(rx.has_changed().unwrap());
+        rx.borrow_and_update();
+        assert!(item(&queue, id).error.is_none());
+
+        queue.apply(id, &progress(0, 20));
+        assert!(!rx.has_changed().unwrap());
+        assert_eq!(item(&queue, id).bytes, 20);
+    }
+
+    #[test]
+    fn rename_is_sent_with_start() {
+        let queue = TransferQueue::new();
+        let id = enqueue(&queue, 1, &CancelFlags::new())[0];
+        let mut rx = queue.subscribe();
+        rx.borrow_and_update();
+
+        queue.apply(
+            id,
+            &TransferEvent::Renamed {
+                index: 0,
+                target: "file0 (1).txt".to_string(),
+            },
+        );
+        assert!(!rx.has_changed().unwrap());
+        assert_eq!(item(&queue, id).renamed.as_deref(), Some("file0 (1).txt"));
+
+        queue.apply(id, &started(0));
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(item(&queue, id).state, ItemState::Running);
+    }
+
+    #[test]
+    fn enqueue_keeps_limited_history() {
+        let queue = TransferQueue::new();
+        let ids = enqueue(&queue, HISTORY_LIMIT + 5, &CancelFlags::new());
+        for (index, &id) in ids.iter().enumerate() {
+            finish(
+                &queue,
+                id,
+                TransferEvent::Completed {
+                    index,
+                    name: format!("file{index}.txt"),
+                    bytes: 100,
+                    duration: Duration::from_secs(1),
+                },
+            );
+        }
+
+        let pending = enqueue(&queue, 1, &CancelFlags::new())[0];
+        let items = queue.subscribe().borrow().clone();
+        assert_eq!(items.len(), HISTORY_LIMIT + 1);
+        // Уходят самые старые завершённые, ожидающий остаётся
+        assert!(items.iter().all(|i| !ids[..5].contains(&i.id)));
+        assert_eq!(items.back().map(|i| i.id), Some(pending));
+        assert_eq!(item(&queue, pending).state, ItemState::Pending);
+    }
+
+    #[test]
+    fn only_failed_or_cancelled_files_are_retried() {
+        let queue = TransferQueue::new();
+        let ids = enqueue(&queue, 4, &CancelFlags::new());
+        finish(
+            &queue,
+            ids[1],
+            TransferEvent::Completed {
+                index: 1,
+                name: "file1.txt".to_string(),
+                bytes: 100,
+                duration: Duration::from_secs(1),
+            },
+        );
+        finish(
+            &queue,
+            ids[2],
+            TransferEvent::Failed {
+                index: 2,
+                name: "file2.txt".to_string(),
+                error: FsError::Timeout,
+            },
+        );
+        queue.cancel(ids[3]).unwrap();
+
+        for &id in &ids[..2] {
+            assert!(matches!(
+                queue.take_for_retry(id),
+                Err(AppError::BadRequest(_))
+            ));
+        }
+        for &id in &ids[2..] {
+            assert_eq!(queue.take_for_retry(id).unwrap().id, id);
+        }
+
+        // Взятые для повтора файлы из очереди убраны
+        let left: Vec<u64> = queue.subscribe().borrow().iter().map(|i| i.id).collect();
+        assert_eq!(left, ids[..2]);
+        assert!(matches!(
+            queue.take_for_retry(ids[2]),
+            Err(AppError::TransferNotFound(_))
+        ));
+    }
+}
@@ -1,57 +1,245 @@
-use std::{convert::Infallible, time::Duration};
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
 
 use askama::Template;
 use axum::{
-    extract::State,
-    response::{Html, IntoResponse, Sse, sse::Event},
+    Extension,
+    extract::{ConnectInfo, Multipart, Query, State},
+    http::StatusCode,
+    response::{
+        Html, IntoResponse, Response, Sse,
+        sse::{Event, KeepAlive},
+    },
+};
+use axum_extra::extract::{CookieJar, Form};
+use ftp_fs::{
+    CancelFlags, ConflictPolicy, FileSystem, FsError, FtpConnectParams, FtpFs, FtpMode, LocalFs,
+    Netrc, RetryClass, RetryPolicy, TransferEvent, TransferOptions, TransferReport, format_size,
 };
-use axum_extra::extract::Form;
-use ftp_fs::{FileSystem, FtpConnectParams, FtpFs};
 use futures_util::{Stream, StreamExt};
 use serde::Deserialize;
-use tokio_stream::wrappers::IntervalStream;
+use tokio::sync::{Mutex, mpsc};
+use tokio_stream::wrappers::WatchStream;
+use utoipa::ToSchema;
 
 use crate::{
+    config::RetrySettings,
+    conflicts::ConflictMode,
+    csrf::{self, CsrfToken, SessionId},
     error::AppError,
+    profiles::{Profile, ProtocolOptions, filezilla},
+    queue::TransferDirs,
     state::AppState,
-    templates::{FilesTableTemplate, IndexTemplate, LocalFilesTableTemplate},
+    templates::{
+        ConflictDialogTemplate, FilesTableTemplate, ImportRow, IndexTemplate,
+        LocalFilesTableTemplate, ProfileFormTemplate, ProfileImportTemplate, ProfilePickerTemplate,
+        ProfilesTemplate, ServerInfoTemplate, StatusFooterTemplate, TransferQueueTemplate,
+        TransferReportTemplate,
+    },
 };
 
 // ---------------------------------------------------------------------------
 // Form types
 // ---------------------------------------------------------------------------
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct ConnectForm {
     pub host: String,
     pub port: u16,
+    /// Пустой логин берётся из `.netrc`.
+    #[serde(default)]
     pub username: String,
+    /// Пустой пароль берётся из `.netrc`.
+    #[serde(default)]
+    #[schema(format = Password)]
     pub password: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct ChangeDirectoryForm {
+    /// Имя поддиректории, `".."` или абсолютный путь.
     pub directory: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UploadForm {
+    /// Имена файлов в текущей локальной директории.
     #[serde(default)]
     pub files: Vec<String>,
+    /// Что делать с файлами, которые уже есть на сервере, вместо
+    /// `[transfers] conflict` конфигурации.
+    #[serde(default)]
+    pub conflict: Option<ConflictMode>,
+    /// Повторы для этой передачи вместо `[transfers.retry]` конфигурации.
+    #[serde(default)]
+    pub retry: Option<RetryForm>,
+    /// Лимит скорости этой передачи в КБ/с (0 — без ограничения) вместо
+    /// `per_transfer_kb_per_sec` конфигурации. Общий лимит действует всегда.
+    #[serde(default)]
+    pub limit_kb_per_sec: Option<u64>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct DownloadForm {
+    /// Имена файлов в текущей удалённой директории.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Что делать с файлами, которые уже есть в локальной директории,
+    /// вместо `[transfers] conflict` конфигурации.
+    #[serde(default)]
+    pub conflict: Option<ConflictMode>,
+    /// Повторы для этой передачи вместо `[transfers.retry]` конфигурации.
+    #[serde(default)]
+    pub retry: Option<RetryForm>,
+    /// Лимит скорости этой передачи в КБ/с (0 — без ограничения) вместо
+    /// `per_transfer_kb_per_sec` конфигурации. Общий лимит действует всегда.
+    #[serde(default)]
+    pub limit_kb_per_sec: Option<u64>,
+}
+
+/// Наибольшее число попыток на файл, которое можно запросить.
+const MAX_RETRY_ATTEMPTS: u32 = 10;
+
+/// Наибольшая пауза перед повтором, которую можно запросить, в миллисекундах.
+const MAX_RETRY_BACKOFF_MS: u64 = 10 * 60 * 1000;
+
+/// Повторы передачи; незаданные поля берутся из конфигурации.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RetryForm {
+    /// Сколько всего попыток на файл, включая первую; 1 — без повторов.
+    #[schema(minimum = 1, maximum = 10)]
+    pub max_attempts: Option<u32>,
+    /// Пауза перед первым повтором в миллисекундах, дальше вдвое дольше.
+    pub initial_backoff_ms: Option<u64>,
+    /// Верхняя граница паузы в миллисекундах.
+    pub max_backoff_ms: Option<u64>,
+    /// Какие ошибки повторять.
+    pub retry_on: Option<Vec<RetryClass>>,
+}
+
+impl RetryForm {
+    /// Политика повторов: поля формы поверх `defaults`.
+    pub fn policy(self, defaults: &RetrySettings) -> Result<RetryPolicy, AppError> {
+        let max_attempts = self.max_attempts.unwrap_or(defaults.max_attempts);
+        if !(1..=MAX_RETRY_ATTEMPTS).contains(&max_attempts) {
+            return Err(AppError::BadRequest(format!(
+                "max_attempts должно быть от 1 до {}",
+                MAX_RETRY_ATTEMPTS
+            )));
+        }
+        let initial_backoff_ms = self
+            .initial_backoff_ms
+            .unwrap_or(defaults.initial_backoff_ms);
+        let max_backoff_ms = self.max_backoff_ms.unwrap_or(defaults.max_backoff_ms);
+        if initial_backoff_ms.max(max_backoff_ms) > MAX_RETRY_BACKOFF_MS {
+            return Err(AppError::BadRequest(format!(
+                "пауза перед повтором не может превышать {} мс",
+                MAX_RETRY_BACKOFF_MS
+            )));
+        }
+        Ok(RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(initial_backoff_ms),
+            max_backoff: Duration::from_millis(max_backoff_ms),
+            retry_on: self.retry_on.unwrap_or_else(|| defaults.retry_on.clone()),
+        })
+    }
+}
+
+/// Параметры передачи: конфигурация, поверх неё — повторы и лимит
+/// скорости из запроса. Политику конфликтов задаёт [`handle_transfer`].
+pub(crate) fn transfer_options(
+    state: &AppState,
+    retry: Option<RetryForm>,
+    limit_kb_per_sec: Option<u64>,
+) -> Result<TransferOptions, AppError> {
+    let defaults = &state.config.transfers.retry;
+    let retry = match retry {
+        Some(retry) => retry.policy(defaults)?,
+        None => defaults.policy(),
+    };
+    let settings = &state.config.transfers;
+    Ok(TransferOptions {
+        retry,
+        limiters: state.bandwidth.limiters(limit_kb_per_sec),
+        atomic_upload: settings
+            .atomic_uploads
+            .then(|| settings.upload_temp_name.clone()),
+        ..TransferOptions::default()
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ProfileForm {
+    #[serde(default)]
+    pub original_name: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub mode: FtpMode,
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub remote_dir: String,
+    #[serde(default)]
+    pub local_dir: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileNameForm {
+    pub profile: String,
+}
+
+#[derive(Deserialize)]
+pub struct ImportApplyForm {
+    pub token: String,
+}
+
+/// Файл в очереди передач.
+#[derive(Deserialize)]
+pub struct QueueItemForm {
+    pub id: u64,
+}
+
+/// Общий лимит скорости из панели на странице.
+#[derive(Deserialize)]
+pub struct BandwidthForm {
+    /// КБ/с; пусто или 0 — без ограничения.
+    pub limit_kb_per_sec: Option<u64>,
+    /// Вернуться к лимиту из конфигурации и расписания.
+    #[serde(default)]
+    pub schedule: bool,
+}
+
+/// Ответ на вопрос о конфликтах: политика для каждого файла.
+#[derive(Deserialize)]
+pub struct ResolveConflictsForm {
+    pub id: u64,
+    /// Имена файлов и политики для них, попарно.
     #[serde(default)]
     pub files: Vec<String>,
+    #[serde(default)]
+    pub policies: Vec<ConflictPolicy>,
+    /// Одна политика для всех файлов вместо `policies`.
+    pub all: Option<ConflictPolicy>,
 }
 
 // ---------------------------------------------------------------------------
 // Handlers
 // ---------------------------------------------------------------------------
 
-pub async fn index() -> impl IntoResponse {
-    Html(IndexTemplate {}.render().unwrap())
+pub async fn index(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    token: Option<Extension<CsrfToken>>,
+) -> impl IntoResponse {
+    let (jar, CsrfToken(csrf_token)) =
+        csrf::page_session(&state, jar, token.map(|Extension(token)| token)).await;
+    (jar, Html(IndexTemplate { csrf_token }.render().unwrap()))
 }
 
 /// Helper to get a mutable reference to the active FtpFs connection.
@@ -61,65 +249,271 @@ async fn get_ftp<'a>(
     guard.as_mut().ok_or(AppError::NotConnected)
 }
 
+/// Выполнить `$op` над соединением `$ftp`; если сервер закрыл сессию
+/// (421 или обрыв), переподключиться и повторить её один раз.
+///
+/// Неудачное переподключение отмечается в `$state.status`: подвал покажет
+/// разрыв, а следующая операция попробует переподключиться снова.
+macro_rules! with_reconnect {
+    ($state:expr, $ftp:ident, $op:expr) => {
+        match $op {
+            Err(e) if e.is_connection_lost() => {
+                log::info!("Сессия FTP потеряна ({}), переподключение", e);
+                if let Err(e) = $ftp.reconnect().await {
+                    let e = $crate::error::AppError::from(e);
+                    $state.status.set_disconnected(Some(e.to_string()));
+                    return Err(e.into());
+                }
+                $state.status.set_connected($ftp.capabilities().clone());
+                $op
+            }
+            result => result,
+        }
+    };
+}
+pub(crate) use with_reconnect;
+
 /// Список файлов удалённой ФС (FTP).
+///
+/// Без подключения — не ошибка, а пустая панель: список запрашивается
+/// при каждой загрузке страницы.
 pub async fn list_handler(State(state): State<AppState>) -> Result<Html<String>, AppError> {
     let mut ftp_guard = state.ftp.lock().await;
-    let ftp = get_ftp(&mut ftp_guard).await?;
-    let files = ftp.list().await?;
+    let Some(ftp) = ftp_guard.as_mut() else {
+        return Ok(Html("<li>Нет активного соединения</li>".to_string()));
+    };
+    let files = with_reconnect!(state, ftp, ftp.list().await)?;
     let html = FilesTableTemplate { files }.render().unwrap();
     Ok(Html(html))
 }
 
+/// Панель «О сервере»: приветствие и возможности, выясненные при входе.
+///
+/// Они запомнены в статусе, так что панель не ждёт соединения, занятого
+/// передачей.
+pub async fn server_info(State(state): State<AppState>) -> Html<String> {
+    match state.status.server() {
+        Some(server) => Html(ServerInfoTemplate::new(&server).render().unwrap()),
+        None => Html("<p>Нет активного соединения</p>".to_string()),
+    }
+}
+
 /// Список файлов локальной ФС.
-pub async fn list_local(State(state): State<AppState>) -> Result<Html<String>, AppError> {
-    let mut local = state.local_fs.lock().await;
+pub async fn list_local(
+    State(state): State<AppState>,
+    session: Option<Extension<SessionId>>,
+) -> Result<Html<String>, AppError> {
+    let local = state.local_fs_for(session.as_deref()).await;
+    let mut local = local.lock().await;
     let files = local.list().await?;
-    let html = LocalFilesTableTemplate { files }.render().unwrap();
+    let path = local.current_dir().await?;
+    let html = LocalFilesTableTemplate { path, files }.render().unwrap();
     Ok(Html(html))
 }
 
 /// Смена директории в локальной ФС.
 pub async fn change_local_directory(
     State(state): State<AppState>,
+    session: Option<Extension<SessionId>>,
     Form(form): Form<ChangeDirectoryForm>,
 ) -> Result<Html<String>, AppError> {
-    let mut local = state.local_fs.lock().await;
-    if let Err(e) = local.change_dir(&form.directory).await {
-        log::warn!("Ошибка смены локальной директории: {}", e);
-    }
+    let local = state.local_fs_for(session.as_deref()).await;
+    let mut local = local.lock().await;
+    local.change_dir(&form.directory).await?;
     Ok(Html(
         "<div hx-get='/local_list' hx-trigger='load'></div>".to_string(),
     ))
 }
 
+/// Открыть сессии `session` локальную ФС в своём корне пользователя,
+/// если для логина и хоста подключения `ftp` он настроен; без `ftp`
+/// (отключение) или без сессии (JSON API) — общий корень.
+///
+/// Корни, выданные прежним подключением, у всех сессий отзываются.
+pub(crate) async fn switch_local_root(
+    state: &AppState,
+    session: Option<&SessionId>,
+    ftp: Option<&FtpFs>,
+) {
+    let root = ftp.and_then(|ftp| state.local_roots.user_root(ftp.host(), ftp.username()));
+    let local = root.and_then(|root| match LocalFs::new(root.to_path_buf()) {
+        Ok(fs) => Some(fs),
+        Err(e) => {
+            log::error!("Корень локальной ФС {} недоступен: {}", root.display(), e);
+            None
+        }
+    });
+    let id = session.map(|session| session.0.as_str());
+    state.sessions.set_local_fs(id, local).await;
+}
+
+/// Проверки и подключение к FTP-серверу: защита от перебора, политика
+/// адресов, затем само подключение.
+///
+/// Отказ по блокировке — [`AppError::Throttled`], по политике адресов —
+/// [`AppError::Target`].
+pub(crate) async fn establish_connection(
+    state: &AppState,
+    client: SocketAddr,
+    params: FtpConnectParams,
+) -> Result<FtpFs, AppError> {
+    let keys = [
+        format!("ip:{}", client.ip()),
+        format!("host:{}", params.host.to_ascii_lowercase()),
+    ];
+    if let Err(left) = state.throttle.check(&keys).await {
+        return Err(AppError::Throttled(left));
+    }
+
+    let connected = match state
+        .config
+        .targets
+        .resolve(&params.host, params.port)
+        .await
+    {
+        Ok(addr) => FtpFs::connect_to(addr, params)
+            .await
+            .map_err(AppError::from),
+        Err(e) => Err(AppError::from(e)),
+    };
+
+    // Неудача уже учтена в `check`
+    if connected.is_ok() {
+        state.throttle.record_success(&keys).await;
+    }
+    connected
+}
+
+/// Подставить недостающие логин и пароль из `.netrc`, если он настроен.
+///
+/// Запись `default` используется, только если это разрешено
+/// `netrc_use_default`.
+fn fill_from_netrc(state: &AppState, params: &mut FtpConnectParams) {
+    let Some(path) = &state.config.netrc_file else {
+        return;
+    };
+    if !path.exists() {
+        return;
+    }
+    match Netrc::from_file(path) {
+        Ok(netrc) => {
+            let entry = netrc.lookup(&params.host).or_else(|| {
+                netrc
+                    .default_entry()
+                    .filter(|_| state.config.netrc_use_default)
+            });
+            if entry.is_some_and(|entry| params.fill_from_netrc_entry(entry)) {
+                log::info!(
+                    "Учётные данные для {} взяты из {}",
+                    params.host,
+                    path.display()
+                );
+            }
+        }
+        Err(e) => log::warn!("Ошибка чтения {}: {}", path.display(), e),
+    }
+}
+
+/// Параметры подключения из формы: таймаут из конфигурации, пустые
+/// логин и пароль — из `.netrc`.
+pub(crate) fn connect_params(state: &AppState, form: ConnectForm) -> FtpConnectParams {
+    let mut params = FtpConnectParams::new(form.host, form.port, form.username, form.password);
+    params.timeout_secs = state.config.connect_timeout_secs;
+    if !params.host.is_empty() && (params.username.is_empty() || params.password.is_empty()) {
+        fill_from_netrc(state, &mut params);
+    }
+    params
+}
+
 /// Подключение к FTP-серверу.
 pub async fn connect_handler(
     State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    session: Option<Extension<SessionId>>,
     Form(form): Form<ConnectForm>,
 ) -> Result<Html<String>, AppError> {
-    *state.connection_error.lock().await = None;
-
-    let params = FtpConnectParams::new(form.host, form.port, form.username, form.password);
-
-    match FtpFs::connect(params).await {
+    let params = connect_params(&state, form);
+    match establish_connection(&state, client, params).await {
         Ok(ftp) => {
+            switch_local_root(&state, session.as_deref(), Some(&ftp)).await;
+            let server = ftp.capabilities().clone();
             *state.ftp.lock().await = Some(ftp);
+            state.status.set_connected(server);
             Ok(Html(
                 r#"<div hx-get="/list" hx-trigger="load"></div>"#.to_string(),
             ))
         }
         Err(e) => {
-            let msg = e.to_string();
-            *state.connection_error.lock().await = Some(msg.clone());
-            Ok(Html(format!("<p>{}</p>", msg)))
+            state.status.set_error(e.to_string());
+            Err(e)
         }
     }
 }
 
+/// Подключение по сохранённому профилю.
+///
+/// После входа применяются режим канала данных и начальные директории профиля.
+pub async fn connect_profile(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    session: Option<Extension<SessionId>>,
+    Form(form): Form<ProfileNameForm>,
+) -> Result<Response, AppError> {
+    let (profile, params) = state.profiles.lock().await.connect_params(&form.profile)?;
+
+    let mut ftp = match establish_connection(&state, client, params).await {
+        Ok(ftp) => ftp,
+        Err(e) => {
+            state.status.set_error(e.to_string());
+            return Err(e);
+        }
+    };
+
+    ftp.set_mode(profile.options.mode);
+    if !profile.remote_dir.is_empty()
+        && let Err(e) = ftp.change_dir(&profile.remote_dir).await
+    {
+        log::warn!(
+            "Профиль {}: ошибка перехода в {}: {}",
+            profile.name,
+            profile.remote_dir,
+            e
+        );
+    }
+    switch_local_root(&state, session.as_deref(), Some(&ftp)).await;
+    let server = ftp.capabilities().clone();
+    *state.ftp.lock().await = Some(ftp);
+    state.status.set_connected(server);
+
+    if !profile.local_dir.is_empty() {
+        // Начальная локальная директория отсчитывается от корня LocalFs
+        let local_dir = format!("/{}", profile.local_dir.trim_start_matches('/'));
+        let local = state.local_fs_for(session.as_deref()).await;
+        if let Err(e) = local.lock().await.change_dir(&local_dir).await {
+            log::warn!(
+                "Профиль {}: ошибка перехода в {}: {}",
+                profile.name,
+                local_dir,
+                e
+            );
+        }
+    }
+
+    Ok((
+        [("HX-Trigger", "refreshRemote, refreshLocal")],
+        Html(String::new()),
+    )
+        .into_response())
+}
+
 /// Отключение от FTP-сервера.
-pub async fn disconnect_handler(State(state): State<AppState>) -> Result<Html<String>, AppError> {
+pub async fn disconnect_handler(
+    State(state): State<AppState>,
+    session: Option<Extension<SessionId>>,
+) -> Result<Html<String>, AppError> {
     let ftp_opt = state.ftp.lock().await.take();
-    *state.connection_error.lock().await = None;
+    state.status.set_disconnected(None);
+    switch_local_root(&state, session.as_deref(), None).await;
 
     if let Some(ftp) = ftp_opt {
         ftp.disconnect().await.unwrap_or_else(|e| {
@@ -139,118 +533,598 @@ pub async fn change_directory_handler(
 ) -> Result<Html<String>, AppError> {
     let mut ftp_guard = state.ftp.lock().await;
     let ftp = get_ftp(&mut ftp_guard).await?;
-    ftp.change_dir(&form.directory).await?;
+    with_reconnect!(state, ftp, ftp.change_dir(&form.directory).await)?;
     Ok(Html(
         "<div hx-get='/list' hx-trigger='load'></div>".to_string(),
     ))
 }
 
+/// Сколько событий передачи может ждать обработки: дальше передача
+/// притормаживает, пока очередь и подвал не догонят.
+const TRANSFER_EVENTS_BUFFER: usize = 64;
+
 /// Общая логика передачи файлов (скачивание или загрузка)
-async fn handle_transfer(
+///
+/// Ошибка возвращается, только если пачку не удалось начать; итог по
+/// каждому файлу — в [`TransferReport`]. `conflict` — что делать с файлами,
+/// которые уже есть в месте назначения; `None` — как в конфигурации.
+/// `local` — локальная ФС, в корне которой идёт передача; `dirs` —
+/// директории повторяемой передачи, `None` — текущие.
+pub(crate) async fn handle_transfer(
     state: AppState,
+    local: Arc<Mutex<LocalFs>>,
     files: Vec<String>,
     is_upload: bool,
-) -> Result<(), AppError> {
+    mut options: TransferOptions,
+    conflict: Option<ConflictMode>,
+    dirs: Option<TransferDirs>,
+) -> Result<TransferReport, AppError> {
     if files.is_empty() {
-        return Ok(());
+        return Ok(TransferReport::default());
     }
+    let files = ftp_fs::validate_file_names(&files)?;
 
-    let local_path = state.local_fs.lock().await.path().to_path_buf();
-    let mut ftp_guard = state.ftp.lock().await;
+    let dirs = {
+        let local = local.lock().await;
+        let dirs = match dirs {
+            // С тех пор корень мог смениться на корень другого пользователя
+            Some(dirs) if !dirs.local.starts_with(local.root()) => {
+                return Err(AppError::BadRequest(
+                    "Локальная директория передачи больше недоступна".to_string(),
+                ));
+            }
+            Some(dirs) => dirs,
+            None => TransferDirs {
+                local: local.path().to_path_buf(),
+                remote: None,
+            },
+        };
+        if is_upload {
+            // Символические ссылки не должны выводить за корень локальной ФС;
+            // отсутствующие файлы попадут в отчёт как ошибки
+            for file in &files {
+                if let Err(e) = local.resolve_from(&dirs.local, file)
+                    && !matches!(e, FsError::PathNotFound { .. })
+                {
+                    return Err(e.into());
+                }
+            }
+        }
+        dirs
+    };
 
-    if let Some(ftp) = ftp_guard.as_mut() {
-        let transfer_status = state.transfer_status.clone();
+    // Файлы появляются в очереди сразу, ещё до захвата соединения
+    let cancel = CancelFlags::new();
+    let conflict = conflict.unwrap_or(state.config.transfers.conflict);
+    let ids = state
+        .queue
+        .enqueue(&files, is_upload, &cancel, &dirs, &options, conflict);
+    options.conflict = conflict.policy();
+    let result = async {
+        if conflict == ConflictMode::Ask {
+            options.conflicts = ask_conflicts(&state, &dirs, &files, is_upload).await?;
+        }
+        run_transfer(&state, &dirs, &files, &ids, &options, &cancel, is_upload).await
+    }
+    .await;
 
-        // callback для обновления статуса
-        let progress_cb = move |progress: ftp_fs::TransferProgress| {
-            let status = transfer_status.clone();
-            let action = if is_upload {
-                "Загрузка"
-            } else {
-                "Скачивание"
-            };
-            let msg = format!("{}: {}", action, progress.filename);
-            tokio::spawn(async move {
-                *status.lock().await = Some(msg);
-            });
-        };
+    // Статус сбрасывается и после ошибки, иначе подвал «зависнет» на файле
+    state.status.set_transfer(None);
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            state.queue.fail(&ids, &e);
+            return Err(e);
+        }
+    };
+    if report.connection_lost() {
+        state
+            .status
+            .set_disconnected(Some("Соединение с сервером потеряно".to_string()));
+    }
+    Ok(report)
+}
+
+/// Найти файлы пачки, которые уже есть в месте назначения, и спросить
+/// пользователя, что с ними делать; возвращает политики по именам файлов.
+///
+/// Соединение на время вопроса не занято: другие запросы к серверу
+/// выполняются, пока пользователь думает.
+async fn ask_conflicts(
+    state: &AppState,
+    dirs: &TransferDirs,
+    files: &[String],
+    is_upload: bool,
+) -> Result<HashMap<String, ConflictPolicy>, AppError> {
+    let conflicts = {
+        let mut ftp_guard = state.ftp.lock().await;
+        let ftp = get_ftp(&mut ftp_guard).await?;
+        let previous = enter_remote_dir(state, ftp, dirs.remote.as_deref()).await?;
+        let conflicts = with_reconnect!(
+            state,
+            ftp,
+            ftp.find_conflicts(&dirs.local, files, is_upload).await
+        );
+        leave_remote_dir(ftp, previous).await;
+        conflicts?
+    };
+    if conflicts.is_empty() {
+        return Ok(HashMap::new());
+    }
+    log::info!(
+        "Файлов уже в месте назначения: {}, ждём ответа",
+        conflicts.len()
+    );
+    Ok(state.conflicts.ask(is_upload, conflicts).await)
+}
+
+/// Перейти на сервере в директорию пачки `dir`; возвращает директорию,
+/// в которую надо вернуться после неё ([`leave_remote_dir`]).
+async fn enter_remote_dir(
+    state: &AppState,
+    ftp: &mut FtpFs,
+    dir: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    let previous = with_reconnect!(state, ftp, ftp.pwd().await)?;
+    if previous == dir {
+        return Ok(None);
+    }
+    with_reconnect!(state, ftp, ftp.change_dir(dir).await)?;
+    Ok(Some(previous))
+}
+
+/// Вернуться в директорию, которую пользователь видит на панели.
+async fn leave_remote_dir(ftp: &mut FtpFs, previous: Option<String>) {
+    if let Some(dir) = previous
+        && let Err(e) = ftp.change_dir(&dir).await
+    {
+        log::warn!("Не удалось вернуться в {}: {}", dir, e);
+    }
+}
+
+/// Передать файлы, уже поставленные в очередь под идентификаторами `ids`.
+///
+/// События передачи применяются к очереди и подвалу по мере поступления и
+/// складываются в отчёт.
+async fn run_transfer(
+    state: &AppState,
+    dirs: &TransferDirs,
+    files: &[String],
+    ids: &[u64],
+    options: &TransferOptions,
+    cancel: &CancelFlags,
+    is_upload: bool,
+) -> Result<TransferReport, AppError> {
+    let mut ftp_guard = state.ftp.lock().await;
+    let ftp = get_ftp(&mut ftp_guard).await?;
+    let previous = enter_remote_dir(state, ftp, dirs.remote.as_deref()).await?;
+    if dirs.remote.is_none()
+        && let Ok(dir) = ftp.pwd().await
+    {
+        state.queue.set_remote_dir(ids, &dir);
+    }
+    let local_path = dirs.local.as_path();
+    let (events_tx, mut events) = mpsc::channel(TRANSFER_EVENTS_BUFFER);
 
+    let transfer = async {
         if is_upload {
-            ftp.upload(&local_path, &files, progress_cb).await?;
+            ftp.upload(local_path, files, options, events_tx, cancel)
+                .await
         } else {
-            ftp.download(&local_path, &files, progress_cb).await?;
+            ftp.download(local_path, files, options, events_tx, cancel)
+                .await
         }
-    }
+    };
+    let track = async {
+        let action = if is_upload {
+            "Загрузка"
+        } else {
+            "Скачивание"
+        };
+        let mut report = TransferReport::default();
+        while let Some(event) = events.recv().await {
+            if let TransferEvent::Started { name, .. } = &event {
+                state
+                    .status
+                    .set_transfer(Some(format!("{}: {}", action, name)));
+            }
+            if let Some(&id) = ids.get(event.index()) {
+                state.queue.apply(id, &event);
+            }
+            report.record(event);
+        }
+        report
+    };
 
-    *state.transfer_status.lock().await = None;
-    Ok(())
+    let (result, report) = tokio::join!(transfer, track);
+    leave_remote_dir(ftp, previous).await;
+    result?;
+    Ok(report)
 }
 
 /// Загрузка выбранных файлов на FTP-сервер.
+///
+/// Список обновляется и после ошибки: часть файлов могла успеть загрузиться.
 pub async fn upload_handler(
     State(state): State<AppState>,
+    session: Option<Extension<SessionId>>,
     Form(form): axum_extra::extract::Form<UploadForm>,
-) -> axum::response::Response {
-    let _ = handle_transfer(state, form.files, true).await;
-    ([("HX-Trigger", "refreshRemote")], Html("".to_string())).into_response()
+) -> Response {
+    let trigger = [("HX-Trigger", "refreshRemote")];
+    let options = match transfer_options(&state, form.retry, form.limit_kb_per_sec) {
+        Ok(options) => options,
+        Err(e) => return e.into_response(),
+    };
+    let local = state.local_fs_for(session.as_deref()).await;
+    match handle_transfer(state, local, form.files, true, options, form.conflict, None).await {
+        Ok(report) => (trigger, transfer_report(report, true)).into_response(),
+        Err(e) => (trigger, e).into_response(),
+    }
 }
 
 /// Скачивание выбранных файлов с FTP-сервера.
+///
+/// Список обновляется и после ошибки: часть файлов могла успеть скачаться.
 pub async fn download_handler(
     State(state): State<AppState>,
+    session: Option<Extension<SessionId>>,
     Form(form): axum_extra::extract::Form<DownloadForm>,
-) -> axum::response::Response {
-    let _ = handle_transfer(state, form.files, false).await;
-    ([("HX-Trigger", "refreshLocal")], Html("".to_string())).into_response()
+) -> Response {
+    let trigger = [("HX-Trigger", "refreshLocal")];
+    let options = match transfer_options(&state, form.retry, form.limit_kb_per_sec) {
+        Ok(options) => options,
+        Err(e) => return e.into_response(),
+    };
+    let local = state.local_fs_for(session.as_deref()).await;
+    match handle_transfer(
+        state,
+        local,
+        form.files,
+        false,
+        options,
+        form.conflict,
+        None,
+    )
+    .await
+    {
+        Ok(report) => (trigger, transfer_report(report, false)).into_response(),
+        Err(e) => (trigger, e).into_response(),
+    }
+}
+
+/// Отменить файл в очереди передач.
+pub async fn cancel_queued(
+    State(state): State<AppState>,
+    Form(form): Form<QueueItemForm>,
+) -> Result<StatusCode, AppError> {
+    state.queue.cancel(form.id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Повторить неудавшийся или отменённый файл из очереди — из тех же
+/// директорий, что и в первый раз.
+///
+/// Отвечает так же, как `/upload` и `/download`: отчётом и обновлением списка.
+pub async fn retry_queued(
+    State(state): State<AppState>,
+    session: Option<Extension<SessionId>>,
+    Form(form): Form<QueueItemForm>,
+) -> Response {
+    let item = match state.queue.take_for_retry(form.id) {
+        Ok(item) => item,
+        Err(e) => return e.into_response(),
+    };
+    let is_upload = item.is_upload;
+    let trigger = [(
+        "HX-Trigger",
+        if is_upload {
+            "refreshRemote"
+        } else {
+            "refreshLocal"
+        },
+    )];
+    let local = state.local_fs_for(session.as_deref()).await;
+    match handle_transfer(
+        state,
+        local,
+        vec![item.name],
+        is_upload,
+        item.options,
+        Some(item.conflict),
+        Some(item.dirs),
+    )
+    .await
+    {
+        Ok(report) => (trigger, transfer_report(report, is_upload)).into_response(),
+        Err(e) => (trigger, e).into_response(),
+    }
+}
+
+/// Убрать завершённые файлы из очереди передач.
+pub async fn clear_queue(State(state): State<AppState>) -> StatusCode {
+    state.queue.clear_finished();
+    StatusCode::NO_CONTENT
+}
+
+/// Сменить общий лимит скорости; действует и на идущие передачи.
+///
+/// Новый лимит приходит в подвал через SSE.
+pub async fn set_bandwidth(
+    State(state): State<AppState>,
+    Form(form): Form<BandwidthForm>,
+) -> StatusCode {
+    let manual = (!form.schedule).then(|| form.limit_kb_per_sec.unwrap_or(0));
+    let limit = state.bandwidth.set_manual(manual);
+    log::info!(
+        "Общий лимит скорости: {}",
+        limit.map_or("без ограничения".to_string(), |l| format!(
+            "{}/с",
+            format_size(l)
+        ))
+    );
+    state.status.set_bandwidth(limit);
+    StatusCode::NO_CONTENT
+}
+
+/// Ответить на вопрос о конфликтах; передача продолжится сама.
+pub async fn resolve_conflicts(
+    State(state): State<AppState>,
+    Form(form): Form<ResolveConflictsForm>,
+) -> Result<StatusCode, AppError> {
+    let decisions = match form.all {
+        Some(policy) => form.files.into_iter().map(|f| (f, policy)).collect(),
+        None => form.files.into_iter().zip(form.policies).collect(),
+    };
+    state.conflicts.answer(form.id, decisions)?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+/// Отчёт о пачке в `#notifications`, как и сообщения об ошибках.
+fn transfer_report(report: TransferReport, is_upload: bool) -> Response {
+    if report.items.is_empty() {
+        return Html(String::new()).into_response();
+    }
+    let html = TransferReportTemplate::new(report, is_upload)
+        .render()
+        .unwrap();
+    (
+        [
+            ("HX-Retarget", "#notifications"),
+            ("HX-Reswap", "innerHTML"),
+        ],
+        Html(html),
+    )
+        .into_response()
+}
+
+/// Выпадающий список профилей в шапке.
+pub async fn profile_picker(State(state): State<AppState>) -> Html<String> {
+    let names = state
+        .profiles
+        .lock()
+        .await
+        .list()
+        .map(|p| p.name.clone())
+        .collect();
+    Html(ProfilePickerTemplate { names }.render().unwrap())
+}
+
+/// Таблица профилей с формой создания.
+pub async fn profiles_page(State(state): State<AppState>) -> Html<String> {
+    let store = state.profiles.lock().await;
+    Html(ProfilesTemplate::new(&store).render().unwrap())
+}
+
+/// Форма редактирования профиля.
+pub async fn edit_profile(
+    State(state): State<AppState>,
+    Query(form): Query<ProfileNameForm>,
+) -> Result<Html<String>, AppError> {
+    let store = state.profiles.lock().await;
+    let profile = store.get(&form.profile)?;
+    Ok(Html(
+        ProfileFormTemplate::edit(&store, profile).render().unwrap(),
+    ))
+}
+
+/// Создание или обновление профиля.
+pub async fn save_profile(
+    State(state): State<AppState>,
+    Form(form): Form<ProfileForm>,
+) -> Result<Response, AppError> {
+    let mut params = FtpConnectParams::new(form.host.trim(), form.port, form.username, "");
+    if let Some(timeout) = form.timeout_secs {
+        params.timeout_secs = timeout.max(1);
+    }
+    let profile = Profile {
+        name: form.name.trim().to_string(),
+        params: params.into(),
+        options: ProtocolOptions { mode: form.mode },
+        remote_dir: form.remote_dir.trim().to_string(),
+        local_dir: form.local_dir.trim().to_string(),
+    };
+    let original = Some(form.original_name.as_str()).filter(|n| !n.is_empty());
+    let password = Some(form.password).filter(|p| !p.is_empty());
+
+    let mut store = state.profiles.lock().await;
+    store.save(original, profile, password)?;
+
+    let html = ProfilesTemplate::new(&store).render().unwrap();
+    Ok(([("HX-Trigger", "refreshProfiles")], Html(html)).into_response())
+}
+
+/// Удаление профиля вместе с его паролем.
+pub async fn delete_profile(
+    State(state): State<AppState>,
+    Form(form): Form<ProfileNameForm>,
+) -> Result<Response, AppError> {
+    let mut store = state.profiles.lock().await;
+    store.delete(&form.profile)?;
+
+    let html = ProfilesTemplate::new(&store).render().unwrap();
+    Ok(([("HX-Trigger", "refreshProfiles")], Html(html)).into_response())
+}
+
+/// Предпросмотр импорта профилей из `sitemanager.xml` FileZilla.
+pub async fn import_preview(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Html<String>, AppError> {
+    let mut xml = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(e.to_string()))?
+    {
+        if field.name() == Some("sitemanager") {
+            xml = Some(
+                field
+                    .text()
+                    .await
+                    .map_err(|e| AppError::BadRequest(e.to_string()))?,
+            );
+        }
+    }
+    let xml = xml.ok_or_else(|| AppError::BadRequest("файл не выбран".to_string()))?;
+    let plan = filezilla::parse(&xml)
+        .map_err(|e| AppError::BadRequest(format!("не XML FileZilla: {}", e)))?;
+
+    let store = state.profiles.lock().await;
+    let rows = plan
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut row = import_row(entry);
+            row.status = if store.contains(&entry.profile.name) {
+                "заменит существующий".to_string()
+            } else {
+                "новый".to_string()
+            };
+            if entry.password.is_some() && !store.can_store_passwords() {
+                row.notes
+                    .push("мастер-ключ не задан, пароль не сохранится".to_string());
+            }
+            row
+        })
+        .collect();
+    drop(store);
+
+    let skipped = plan.skipped.clone();
+    let html = ProfileImportTemplate {
+        token: state.imports.insert(plan),
+        rows,
+        skipped,
+        applied: false,
+    };
+    Ok(Html(html.render().unwrap()))
+}
+
+/// Применить импорт из FileZilla и показать отчёт по каждой записи.
+///
+/// Импортируется план, разобранный при предпросмотре; все профили
+/// записываются на диск одним сохранением.
+pub async fn import_apply(
+    State(state): State<AppState>,
+    Form(form): Form<ImportApplyForm>,
+) -> Result<Response, AppError> {
+    let plan = state.imports.take(&form.token).ok_or_else(|| {
+        AppError::BadRequest("предпросмотр импорта устарел, выберите файл заново".to_string())
+    })?;
+
+    let mut store = state.profiles.lock().await;
+    let mut rows = Vec::new();
+    let mut batch = Vec::new();
+    for entry in plan.entries {
+        let mut row = import_row(&entry);
+        let password = entry.password.filter(|_| store.can_store_passwords());
+        row.password = password.is_some();
+        rows.push(row);
+        batch.push((entry.profile, password));
+    }
+    for (row, result) in rows.iter_mut().zip(store.import(batch)?) {
+        row.status = match result {
+            Ok(()) => "импортирован".to_string(),
+            Err(e) => format!("ошибка: {}", e),
+        };
+    }
+
+    let html = ProfileImportTemplate {
+        token: String::new(),
+        rows,
+        skipped: plan.skipped,
+        applied: true,
+    };
+    Ok((
+        [("HX-Trigger", "refreshProfiles")],
+        Html(html.render().unwrap()),
+    )
+        .into_response())
+}
+
+fn import_row(entry: &filezilla::ImportEntry) -> ImportRow {
+    let params = &entry.profile.params;
+    ImportRow {
+        name: entry.profile.name.clone(),
+        server: format!("{}:{}", params.host, params.port),
+        username: params.username.clone(),
+        password: entry.password.is_some(),
+        status: String::new(),
+        notes: entry.notes.clone(),
+    }
+}
+
+/// Интервал комментариев-heartbeat в SSE-потоке: не даёт прокси закрыть
+/// простаивающее соединение.
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
 /// SSE-поток статуса подключения и передачи.
+///
+/// Событие отправляется сразу при изменении [`Status`]; кнопка подключения —
+/// только когда меняется сам факт подключения. Панель очереди передач
+/// приходит событием `queue`, вопросы о конфликтах — событием `conflicts`.
+/// Пока изменений нет, соединение держат комментарии-heartbeat.
 pub async fn events(
     State(state): State<AppState>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let interval = tokio::time::interval(Duration::from_secs(2));
-
-    let stream = IntervalStream::new(interval)
-        .then(move |_| {
-            let state = state.clone();
-            async move {
-                let connected = {
-                    let mut ftp_guard = state.ftp.lock().await;
-                    match ftp_guard.as_mut() {
-                        Some(ftp) => ftp.ping().await,
-                        None => false,
-                    }
-                };
-
-                let footer_html = if connected {
-                    let transfer = state.transfer_status.lock().await.clone();
-                    if let Some(msg) = transfer {
-                        format!("<p>🔄 {}</p>", msg)
-                    } else {
-                        "<p>Подключено к серверу</p>".to_string()
-                    }
-                } else {
-                    let error = state.connection_error.lock().await.clone();
-                    if let Some(err_msg) = error {
-                        format!("<p>❌ Ошибка: {}</p>", err_msg)
-                    } else {
-                        "<p>❌ Нет подключения</p>".to_string()
-                    }
-                };
-
-                let button_html = if connected {
-                    r#"<button class="button" hx-post="/disconnect" hx-target='#remote-list' hx-swap="innerHTML">Отключиться</button>"#
-                } else {
-                    r#"<button class="button" hx-post="/connect" hx-target='#remote-list' hx-swap="innerHTML">Подключиться</button>"#
-                };
-
-                vec![
-                    Event::default().event("footer").data(footer_html),
-                    Event::default().event("button").data(button_html),
-                ]
-            }
-        })
-        .flat_map(|events| futures_util::stream::iter(events).map(Ok));
+    let mut last_connected = None;
+
+    let status = WatchStream::new(state.status.subscribe()).flat_map(move |status| {
+        let mut events = vec![
+            Event::default()
+                .event("footer")
+                .data(StatusFooterTemplate::new(&status).render().unwrap()),
+        ];
+        if last_connected != Some(status.connected) {
+            last_connected = Some(status.connected);
+            events.push(
+                Event::default()
+                    .event("button")
+                    .data(button_html(status.connected)),
+            );
+        }
+        futures_util::stream::iter(events.into_iter().map(Ok))
+    });
+    let queue = WatchStream::new(state.queue.subscribe()).map(|items| {
+        let html = TransferQueueTemplate::new(&items).render().unwrap();
+        Ok(Event::default().event("queue").data(html))
+    });
+    let conflicts = WatchStream::new(state.conflicts.subscribe()).map(|questions| {
+        let html = ConflictDialogTemplate::new(&questions).render().unwrap();
+        Ok(Event::default().event("conflicts").data(html))
+    });
+    let stream =
+        futures_util::stream::select(futures_util::stream::select(status, queue), conflicts);
 
-    Sse::new(stream)
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(HEARTBEAT).text("heartbeat"))
+}
+
+fn button_html(connected: bool) -> &'static str {
+    if connected {
+        r#"<button class="button" hx-post="/disconnect" hx-target='#remote-list' hx-swap="innerHTML">Отключиться</button>"#
+    } else {
+        r#"<button class="button" hx-post="/connect" hx-target='#remote-list' hx-swap="innerHTML">Подключиться</button>"#
+    }
 }
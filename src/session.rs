@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ftp_fs::LocalFs;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// Настройки сессий (секция `[session]` конфигурации).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSettings {
+    /// Имя cookie с идентификатором сессии.
+    pub cookie_name: String,
+    /// Сессия, простаивающая дольше этого срока (в секундах), удаляется.
+    pub idle_timeout_secs: u64,
+    /// Выставлять cookie флаг `Secure` (только HTTPS).
+    pub secure_cookie: bool,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            cookie_name: "ftp_session".to_string(),
+            idle_timeout_secs: 12 * 60 * 60,
+            secure_cookie: false,
+        }
+    }
+}
+
+/// Данные одной браузерной сессии.
+#[derive(Clone)]
+pub struct Session {
+    /// CSRF-токен, привязанный к сессии.
+    pub csrf_token: String,
+    /// Локальная ФС в своём корне пользователя, если подключение этой
+    /// сессии его получило; иначе сессия работает в общем корне.
+    local_fs: Option<Arc<Mutex<LocalFs>>>,
+    last_seen: Instant,
+}
+
+/// Хранилище сессий в памяти процесса.
+#[derive(Clone)]
+pub struct SessionStore {
+    settings: Arc<SessionSettings>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+impl SessionStore {
+    pub fn new(settings: SessionSettings) -> Self {
+        Self {
+            settings: Arc::new(settings),
+            sessions: Arc::default(),
+        }
+    }
+
+    pub fn settings(&self) -> &SessionSettings {
+        &self.settings
+    }
+
+    fn idle_ttl(&self) -> Duration {
+        Duration::from_secs(self.settings.idle_timeout_secs)
+    }
+
+    /// Найти сессию по идентификатору и отметить её активность.
+    pub async fn touch(&self, id: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.get_mut(id)?;
+        if session.last_seen.elapsed() > self.idle_ttl() {
+            sessions.remove(id);
+            return None;
+        }
+        session.last_seen = Instant::now();
+        Some(session.clone())
+    }
+
+    /// Создать новую сессию. Возвращает её идентификатор и данные.
+    pub async fn create(&self) -> (String, Session) {
+        let id = random_token();
+        let session = Session {
+            csrf_token: random_token(),
+            local_fs: None,
+            last_seen: Instant::now(),
+        };
+
+        let mut sessions = self.sessions.lock().await;
+        let ttl = self.idle_ttl();
+        sessions.retain(|_, s| s.last_seen.elapsed() <= ttl);
+        sessions.insert(id.clone(), session.clone());
+        (id, session)
+    }
+
+    /// Локальная ФС сессии `id` в своём корне пользователя, если он выдан.
+    pub async fn local_fs(&self, id: &str) -> Option<Arc<Mutex<LocalFs>>> {
+        self.sessions.lock().await.get(id)?.local_fs.clone()
+    }
+
+    /// Выдать сессии `id` локальную ФС в своём корне (`None` — общий корень).
+    ///
+    /// FTP-соединение одно на всех: корень, выданный прежним подключением,
+    /// у остальных сессий отзывается.
+    pub async fn set_local_fs(&self, id: Option<&str>, local: Option<LocalFs>) {
+        let mut sessions = self.sessions.lock().await;
+        for session in sessions.values_mut() {
+            session.local_fs = None;
+        }
+        if let Some(session) = id.and_then(|id| sessions.get_mut(id)) {
+            session.local_fs = local.map(|fs| Arc::new(Mutex::new(fs)));
+        }
+    }
+}
+
+/// 256 случайных бит в hex-представлении.
+pub(crate) fn random_token() -> String {
+    rand::random::<[u8; 32]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_root_belongs_to_the_connecting_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(SessionSettings::default());
+        let (alice, _) = store.create().await;
+        let (other, _) = store.create().await;
+
+        let local = LocalFs::new(dir.path().to_path_buf()).unwrap();
+        store.set_local_fs(Some(&alice), Some(local)).await;
+        assert!(store.local_fs(&alice).await.is_some());
+        assert!(store.local_fs(&other).await.is_none());
+
+        // Подключение из другой сессии отзывает выданный корень
+        store.set_local_fs(Some(&other), None).await;
+        assert!(store.local_fs(&alice).await.is_none());
+        assert!(store.local_fs(&other).await.is_none());
+    }
+}
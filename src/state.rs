@@ -3,6 +3,12 @@ use std::sync::Arc;
 use ftp_fs::LocalFs;
 use tokio::sync::Mutex;
 
+use crate::{
+    bandwidth::Bandwidth, config::Config, conflicts::ConflictPrompts, local_roots::LocalRoots,
+    profiles::ProfileStore, queue::TransferQueue, session::SessionStore, status::StatusChannel,
+    throttle::LoginThrottle, transfers::TransferJobs,
+};
+
 /// Глобальное состояние приложения.
 ///
 /// Разделяется между всеми обработчиками запросов через [`Arc`].
@@ -10,10 +16,78 @@ use tokio::sync::Mutex;
 pub struct AppState {
     /// Активное FTP-соединение (если есть).
     pub ftp: Arc<Mutex<Option<ftp_fs::FtpFs>>>,
-    /// Сообщение об ошибке последнего подключения.
-    pub connection_error: Arc<Mutex<Option<String>>>,
-    /// Навигация по локальной ФС.
+    /// Навигация по локальной ФС в общем корне; сессии со своим корнем
+    /// пользователя — см. [`AppState::local_fs_for`].
     pub local_fs: Arc<Mutex<LocalFs>>,
-    /// Статус текущей передачи файлов (для SSE).
-    pub transfer_status: Arc<Mutex<Option<String>>>,
+    /// Общий корень локальной ФС и корни пользователей FTP.
+    pub local_roots: Arc<LocalRoots>,
+    /// Подключение, ошибка и текущая передача — для SSE-потока `/events`.
+    pub status: Arc<StatusChannel>,
+    /// Браузерные сессии (CSRF-токены).
+    pub sessions: SessionStore,
+    /// Конфигурация сервера (неизменна после старта).
+    pub config: Arc<Config>,
+    /// Счётчики неудачных подключений по IP клиента и целевому хосту.
+    pub throttle: Arc<LoginThrottle>,
+    /// Профили подключений и хранилище их паролей.
+    pub profiles: Arc<Mutex<ProfileStore>>,
+    /// Разобранные файлы FileZilla, ожидающие подтверждения импорта.
+    pub imports: Arc<crate::profiles::filezilla::PendingImports>,
+    /// Очередь передач для панели на странице.
+    pub queue: Arc<TransferQueue>,
+    /// Передачи, запущенные через JSON API.
+    pub transfers: Arc<TransferJobs>,
+    /// Общий ограничитель скорости передач.
+    pub bandwidth: Arc<Bandwidth>,
+    /// Вопросы о файлах, которые уже есть в месте назначения.
+    pub conflicts: Arc<ConflictPrompts>,
+}
+
+impl AppState {
+    /// Локальная ФС сессии: в своём корне пользователя, если подключение
+    /// этой сессии его получило, иначе общая.
+    pub async fn local_fs_for(
+        &self,
+        session: Option<&crate::csrf::SessionId>,
+    ) -> Arc<Mutex<LocalFs>> {
+        let own = match session {
+            Some(session) => self.sessions.local_fs(&session.0).await,
+            None => None,
+        };
+        own.unwrap_or_else(|| Arc::clone(&self.local_fs))
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// Состояние для тестов: конфигурация по умолчанию, локальная ФС,
+    /// профили и хранилище паролей — в `root`.
+    pub fn for_tests(root: &std::path::Path) -> Self {
+        use crate::profiles::ProfileSettings;
+
+        let config = Config::default();
+        let local_roots = LocalRoots::new(root.to_path_buf(), &Default::default()).unwrap();
+        Self {
+            ftp: Arc::new(Mutex::new(None)),
+            local_fs: Arc::new(Mutex::new(local_roots.open_default().unwrap())),
+            local_roots: Arc::new(local_roots),
+            status: Arc::new(StatusChannel::new()),
+            sessions: SessionStore::new(config.session.clone()),
+            profiles: Arc::new(Mutex::new(
+                ProfileStore::open(&ProfileSettings {
+                    file: root.join("profiles.json"),
+                    vault: root.join("vault.json"),
+                    master_key_file: None,
+                })
+                .unwrap(),
+            )),
+            imports: Arc::new(crate::profiles::filezilla::PendingImports::new()),
+            throttle: Arc::new(LoginThrottle::new(config.throttle.clone())),
+            queue: Arc::new(TransferQueue::new()),
+            transfers: Arc::new(TransferJobs::new()),
+            bandwidth: Arc::new(Bandwidth::new(config.transfers.bandwidth.clone())),
+            conflicts: Arc::new(ConflictPrompts::new()),
+            config: Arc::new(config),
+        }
+    }
 }
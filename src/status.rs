@@ -0,0 +1,104 @@
+//! Состояние подключения и передачи, которое показывают подвал и кнопка
+//! подключения.
+//!
+//! Обработчики сообщают об изменениях через [`StatusChannel`], а SSE-поток
+//! `/events` отправляет их клиентам сразу же — без опроса сервера NOOP.
+
+use ftp_fs::ServerCapabilities;
+use tokio::sync::watch;
+
+/// Снимок состояния для подвала страницы.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Есть рабочее FTP-соединение.
+    pub connected: bool,
+    /// Ошибка последнего подключения или причина разрыва.
+    pub connection_error: Option<String>,
+    /// Файл, который передаётся прямо сейчас.
+    pub transfer: Option<String>,
+    /// Общий лимит скорости передач в байтах в секунду.
+    pub bandwidth: Option<u64>,
+    /// Что умеет сервер: запоминается при входе, чтобы отвечать о нём, не
+    /// дожидаясь соединения, занятого передачей.
+    pub server: Option<ServerCapabilities>,
+}
+
+/// Канал изменений [`Status`].
+///
+/// Подписчики получают только последнее значение: частые обновления
+/// прогресса схлопываются, если клиент не успевает их читать.
+pub struct StatusChannel {
+    tx: watch::Sender<Status>,
+}
+
+impl StatusChannel {
+    pub fn new() -> Self {
+        Self {
+            tx: watch::Sender::new(Status::default()),
+        }
+    }
+
+    pub fn get(&self) -> Status {
+        self.tx.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Status> {
+        self.tx.subscribe()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.tx.borrow().connected
+    }
+
+    /// Возможности сервера, если соединение есть.
+    pub fn server(&self) -> Option<ServerCapabilities> {
+        self.tx.borrow().server.clone()
+    }
+
+    /// Соединение установлено (или восстановлено): прежняя ошибка неактуальна.
+    pub fn set_connected(&self, server: ServerCapabilities) {
+        self.update(|s| {
+            s.connected = true;
+            s.connection_error = None;
+            s.server = Some(server);
+        });
+    }
+
+    /// Соединения больше нет; `error` — почему, если это не отключение
+    /// по запросу пользователя.
+    pub fn set_disconnected(&self, error: Option<String>) {
+        self.update(|s| {
+            s.connected = false;
+            s.connection_error = error;
+            s.server = None;
+        });
+    }
+
+    /// Подключение не удалось. Прежнее соединение, если было, остаётся.
+    pub fn set_error(&self, error: String) {
+        self.update(|s| s.connection_error = Some(error));
+    }
+
+    pub fn set_transfer(&self, transfer: Option<String>) {
+        self.update(|s| s.transfer = transfer);
+    }
+
+    pub fn set_bandwidth(&self, limit: Option<u64>) {
+        self.update(|s| s.bandwidth = limit);
+    }
+
+    /// Изменить состояние и разбудить подписчиков, только если оно изменилось.
+    fn update(&self, change: impl FnOnce(&mut Status)) {
+        self.tx.send_if_modified(|status| {
+            let before = status.clone();
+            change(status);
+            *status != before
+        });
+    }
+}
+
+impl Default for StatusChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
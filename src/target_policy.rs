@@ -0,0 +1,328 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use ipnet::IpNet;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Ошибка проверки адреса FTP-сервера.
+#[derive(Debug, Error)]
+pub enum TargetError {
+    #[error("Подключение к {0} запрещено политикой сервера")]
+    Blocked(String),
+
+    #[error("Не удалось разрешить адрес {host}: {source}")]
+    Resolve {
+        host: String,
+        source: std::io::Error,
+    },
+}
+
+/// Диапазон портов: `"21"` или `"2100-2200"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse =
+            |s: &str| u16::from_str(s.trim()).map_err(|_| format!("некорректный порт: {value:?}"));
+        let (start, end) = match value.split_once('-') {
+            Some((a, b)) => (parse(a)?, parse(b)?),
+            None => {
+                let port = parse(&value)?;
+                (port, port)
+            }
+        };
+        if start > end {
+            return Err(format!("пустой диапазон портов: {value:?}"));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Правила, к каким FTP-серверам разрешено подключаться (защита от SSRF).
+///
+/// Секция `[targets]` конфигурации. Имена хостов сравниваются без учёта
+/// регистра, `*.example.com` совпадает с любым поддоменом. Пустые списки
+/// разрешений означают «разрешено всё, что не запрещено».
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetPolicy {
+    pub allow_hosts: Vec<String>,
+    pub deny_hosts: Vec<String>,
+    pub allow_networks: Vec<IpNet>,
+    pub deny_networks: Vec<IpNet>,
+    pub allow_ports: Vec<PortRange>,
+}
+
+/// Сети, закрытые по умолчанию: сам сервер (loopback, `0.0.0.0/8` и `::`
+/// — на Linux подключение к ним попадает на локальный хост), устаревшие
+/// IPv4-совместимые адреса `::a.b.c.d`, частные сети RFC 1918, CGNAT и ULA,
+/// link-local — там живут метаданные облаков.
+const DEFAULT_DENY_NETWORKS: [&str; 12] = [
+    "0.0.0.0/8",
+    "127.0.0.0/8",
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "100.64.0.0/10",
+    "169.254.0.0/16",
+    "::/128",
+    "::1/128",
+    "::/96",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+impl Default for TargetPolicy {
+    fn default() -> Self {
+        Self {
+            allow_hosts: Vec::new(),
+            deny_hosts: Vec::new(),
+            allow_networks: Vec::new(),
+            deny_networks: DEFAULT_DENY_NETWORKS
+                .iter()
+                .map(|net| net.parse().unwrap())
+                .collect(),
+            allow_ports: Vec::new(),
+        }
+    }
+}
+
+impl TargetPolicy {
+    /// Разрешить `host` через DNS и выбрать первый адрес, допустимый политикой.
+    ///
+    /// Проверка выполняется после разрешения имени, поэтому подключаться
+    /// нужно именно к возвращённому адресу, а не к `host` повторно.
+    pub async fn resolve(&self, host: &str, port: u16) -> Result<SocketAddr, TargetError> {
+        let target = format!("{host}:{port}");
+
+        if !self.allow_ports.is_empty() && !self.allow_ports.iter().any(|r| r.contains(port)) {
+            return Err(TargetError::Blocked(target));
+        }
+        if self.deny_hosts.iter().any(|p| host_matches(p, host)) {
+            return Err(TargetError::Blocked(target));
+        }
+
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|source| TargetError::Resolve {
+                host: host.to_string(),
+                source,
+            })?;
+        self.pick(host, port, addrs)
+    }
+
+    /// Первый из адресов `host`, допустимый политикой.
+    fn pick(
+        &self,
+        host: &str,
+        port: u16,
+        addrs: impl IntoIterator<Item = SocketAddr>,
+    ) -> Result<SocketAddr, TargetError> {
+        let target = format!("{host}:{port}");
+        let host_allowed = self.allow_hosts.iter().any(|p| host_matches(p, host));
+        let mut blocked = Vec::new();
+        for addr in addrs {
+            let ip = canonical_ip(addr.ip());
+            if self.ip_allowed(ip, host_allowed) {
+                return Ok(SocketAddr::new(ip, port));
+            }
+            blocked.push(ip.to_string());
+        }
+
+        log::warn!("Подключение к {} заблокировано: {:?}", target, blocked);
+        Err(TargetError::Blocked(match blocked.as_slice() {
+            [] => target,
+            ips => format!("{target} ({})", ips.join(", ")),
+        }))
+    }
+
+    fn ip_allowed(&self, ip: IpAddr, host_allowed: bool) -> bool {
+        if self.deny_networks.iter().any(|n| n.contains(&ip)) {
+            return false;
+        }
+        let no_allow_rules = self.allow_hosts.is_empty() && self.allow_networks.is_empty();
+        no_allow_rules || host_allowed || self.allow_networks.iter().any(|n| n.contains(&ip))
+    }
+}
+
+/// IPv4-mapped IPv6 (`::ffff:10.0.0.1`) проверяется как обычный IPv4.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.ends_with(&format!(".{suffix}")),
+        None => host == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn networks(list: &[&str]) -> Vec<IpNet> {
+        list.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    fn blocked(result: Result<SocketAddr, TargetError>) -> bool {
+        matches!(result, Err(TargetError::Blocked(_)))
+    }
+
+    #[tokio::test]
+    async fn default_policy_denies_internal_networks() {
+        let policy = TargetPolicy::default();
+        for ip in [
+            "127.0.0.1",
+            "0.0.0.0",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "100.127.255.254",
+            "169.254.169.254",
+            "::",
+            "::1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(
+                blocked(policy.resolve(ip, 21).await),
+                "{ip} должен быть закрыт"
+            );
+        }
+        assert_eq!(
+            policy.resolve("203.0.113.5", 21).await.unwrap(),
+            addr("203.0.113.5:21")
+        );
+        assert!(policy.resolve("100.128.0.1", 21).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn allow_networks_restrict_targets() {
+        let policy = TargetPolicy {
+            allow_networks: networks(&["203.0.113.0/24"]),
+            ..TargetPolicy::default()
+        };
+        assert!(policy.resolve("203.0.113.5", 21).await.is_ok());
+        assert!(blocked(policy.resolve("198.51.100.1", 21).await));
+
+        // Запрет сильнее разрешения
+        let policy = TargetPolicy {
+            allow_networks: networks(&["10.0.0.0/8"]),
+            ..TargetPolicy::default()
+        };
+        assert!(blocked(policy.resolve("10.0.0.1", 21).await));
+
+        let policy = TargetPolicy {
+            allow_networks: networks(&["10.0.0.0/8"]),
+            deny_networks: Vec::new(),
+            ..TargetPolicy::default()
+        };
+        assert!(policy.resolve("10.0.0.1", 21).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn allow_ports_restrict_targets() {
+        let policy = TargetPolicy {
+            allow_ports: vec![
+                PortRange::try_from("21".to_string()).unwrap(),
+                PortRange::try_from("2100-2200".to_string()).unwrap(),
+            ],
+            ..TargetPolicy::default()
+        };
+        assert!(policy.resolve("203.0.113.5", 21).await.is_ok());
+        assert!(policy.resolve("203.0.113.5", 2150).await.is_ok());
+        assert!(blocked(policy.resolve("203.0.113.5", 22).await));
+        assert!(blocked(policy.resolve("203.0.113.5", 2201).await));
+        assert!(PortRange::try_from("2200-2100".to_string()).is_err());
+    }
+
+    #[test]
+    fn host_with_several_addresses_uses_first_allowed() {
+        let policy = TargetPolicy::default();
+        let addrs = [
+            addr("127.0.0.1:21"),
+            addr("[fd00::1]:21"),
+            addr("198.51.100.7:21"),
+            addr("203.0.113.5:21"),
+        ];
+        assert_eq!(
+            policy.pick("ftp.example.com", 21, addrs).unwrap(),
+            addr("198.51.100.7:21")
+        );
+
+        let internal = [addr("10.0.0.1:21"), addr("[::1]:21")];
+        match policy.pick("ftp.example.com", 21, internal) {
+            Err(TargetError::Blocked(message)) => {
+                assert!(
+                    message.contains("10.0.0.1") && message.contains("::1"),
+                    "{message}"
+                );
+            }
+            other => panic!("ожидался запрет: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn hosts_are_matched_by_name() {
+        let policy = TargetPolicy {
+            allow_hosts: vec!["*.example.com".to_string()],
+            deny_networks: Vec::new(),
+            ..TargetPolicy::default()
+        };
+        let addrs = [addr("198.51.100.7:21")];
+        assert!(policy.pick("FTP.Example.com.", 21, addrs).is_ok());
+        assert!(blocked(policy.pick("example.org", 21, addrs)));
+        // Разрешённое имя не открывает запрещённые сети
+        let policy = TargetPolicy {
+            allow_hosts: vec!["intranet.example.com".to_string()],
+            ..TargetPolicy::default()
+        };
+        assert!(blocked(policy.pick(
+            "intranet.example.com",
+            21,
+            [addr("10.0.0.1:21")]
+        )));
+    }
+
+    #[tokio::test]
+    async fn denied_host_is_not_resolved() {
+        let policy = TargetPolicy {
+            deny_hosts: vec!["*.internal".to_string()],
+            ..TargetPolicy::default()
+        };
+        assert!(blocked(policy.resolve("files.internal", 21).await));
+    }
+}
@@ -1,9 +1,24 @@
 use askama::Template;
-use ftp_fs::FileEntry;
+use chrono::{DateTime, Local};
+use ftp_fs::{
+    FileEntry, FileStat, FtpConnectParams, FtpMode, ServerCapabilities, SkipReason,
+    TransferOutcome, TransferReport, format_size,
+};
+
+use crate::{
+    conflicts::ConflictQuestion,
+    error::AppError,
+    profiles::{Profile, ProfileStore, filezilla::SkippedEntry},
+    queue::{ItemState, QueueItem},
+    status::Status,
+};
 
 #[derive(Template)]
 #[template(path = "index.html")]
-pub struct IndexTemplate {}
+pub struct IndexTemplate {
+    /// CSRF-токен сессии, HTMX отправляет его в каждом запросе.
+    pub csrf_token: String,
+}
 
 #[derive(Template)]
 #[template(path = "files_table.html")]
@@ -14,5 +29,557 @@ pub struct FilesTableTemplate {
 #[derive(Template)]
 #[template(path = "local_files_table.html")]
 pub struct LocalFilesTableTemplate {
+    /// Текущий путь относительно корня локальной ФС.
+    pub path: String,
     pub files: Vec<FileEntry>,
 }
+
+/// Сообщение об ошибке в `#notifications`.
+#[derive(Template)]
+#[template(path = "notification.html")]
+pub struct NotificationTemplate {
+    pub message: String,
+    /// Код ответа FTP-сервера.
+    pub reply_code: Option<u32>,
+    pub path: Option<String>,
+}
+
+/// Строка отчёта о передаче.
+pub struct TransferRow {
+    pub name: String,
+    /// `transferred`, `skipped` или `failed` — суффикс CSS-класса строки.
+    pub status: &'static str,
+    /// «Передан», причина пропуска или текст ошибки.
+    pub detail: String,
+    pub size: String,
+    pub duration: String,
+}
+
+/// Итог пачки загрузок/скачиваний в `#notifications`.
+#[derive(Template)]
+#[template(path = "transfer_report.html")]
+pub struct TransferReportTemplate {
+    pub title: &'static str,
+    pub rows: Vec<TransferRow>,
+    pub transferred: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Суммарный объём переданных файлов.
+    pub total: String,
+}
+
+impl TransferReportTemplate {
+    pub fn new(report: TransferReport, is_upload: bool) -> Self {
+        let (transferred, skipped, failed) =
+            (report.transferred(), report.skipped(), report.failed());
+        let total = format_size(report.bytes());
+        let rows = report
+            .items
+            .into_iter()
+            .map(|item| {
+                let (status, mut detail) = match item.outcome {
+                    TransferOutcome::Transferred if item.verified => {
+                        ("transferred", "Передан, размер проверен".to_string())
+                    }
+                    TransferOutcome::Transferred => ("transferred", "Передан".to_string()),
+                    TransferOutcome::Skipped(reason) => {
+                        ("skipped", skip_reason_text(reason).to_string())
+                    }
+                    TransferOutcome::Failed(e) => ("failed", AppError::Fs(e).to_string()),
+                };
+                if let Some(target) = &item.renamed {
+                    detail.push_str(&format!(", сохранён как {}", target));
+                }
+                if item.attempts > 1 {
+                    detail.push_str(&format!(" (попыток: {})", item.attempts));
+                }
+                let done = status == "transferred";
+                TransferRow {
+                    name: item.name,
+                    status,
+                    detail,
+                    size: if done {
+                        format_size(item.bytes)
+                    } else {
+                        "—".to_string()
+                    },
+                    duration: if done {
+                        format!("{:.1} с", item.duration.as_secs_f64())
+                    } else {
+                        "—".to_string()
+                    },
+                }
+            })
+            .collect();
+        Self {
+            title: if is_upload {
+                "Загрузка"
+            } else {
+                "Скачивание"
+            },
+            rows,
+            transferred,
+            skipped,
+            failed,
+            total,
+        }
+    }
+}
+
+fn skip_reason_text(reason: SkipReason) -> &'static str {
+    match reason {
+        SkipReason::Directory => "Пропущен: это директория",
+        SkipReason::NotRegularFile => "Пропущен: не обычный файл",
+        SkipReason::Aborted => "Пропущен: соединение с сервером потеряно",
+        SkipReason::Cancelled => "Отменён",
+        SkipReason::Exists => "Пропущен: уже есть в месте назначения",
+        SkipReason::UpToDate => "Пропущен: в месте назначения уже актуальная версия",
+    }
+}
+
+/// Строка панели очереди передач.
+pub struct QueueRow {
+    pub id: u64,
+    pub name: String,
+    pub is_upload: bool,
+    pub direction: &'static str,
+    /// Суффикс CSS-класса: `pending`, `running`, `transferred`, `skipped`,
+    /// `failed` или `cancelled`.
+    pub state: &'static str,
+    /// Процент для `<progress>`; `None` — размер неизвестен.
+    pub percent: Option<u64>,
+    /// Объём, скорость и оставшееся время, либо итог.
+    pub status: String,
+    pub can_cancel: bool,
+    pub can_retry: bool,
+}
+
+/// Строка состояния в подвале, отправляется SSE-событием `footer`.
+///
+/// Текст ошибки и имя файла приходят от FTP-сервера, поэтому они
+/// подставляются только через шаблон с экранированием.
+#[derive(Template)]
+#[template(path = "status_footer.html")]
+pub struct StatusFooterTemplate {
+    pub text: String,
+    /// Общий лимит скорости, уже отформатированный.
+    pub bandwidth: Option<String>,
+}
+
+impl StatusFooterTemplate {
+    pub fn new(status: &Status) -> Self {
+        let text = if status.connected {
+            match &status.transfer {
+                Some(msg) => format!("🔄 {}", msg),
+                None => "Подключено к серверу".to_string(),
+            }
+        } else if let Some(err_msg) = &status.connection_error {
+            format!("❌ Ошибка: {}", err_msg)
+        } else {
+            "❌ Нет подключения".to_string()
+        };
+        Self {
+            text,
+            bandwidth: status.bandwidth.map(format_size),
+        }
+    }
+}
+
+/// Панель очереди передач, отправляется SSE-событием `queue`.
+#[derive(Template)]
+#[template(path = "transfer_queue.html")]
+pub struct TransferQueueTemplate {
+    pub rows: Vec<QueueRow>,
+    pub has_finished: bool,
+}
+
+impl TransferQueueTemplate {
+    pub fn new<'a>(items: impl IntoIterator<Item = &'a QueueItem>) -> Self {
+        let rows: Vec<QueueRow> = items.into_iter().map(queue_row).collect();
+        Self {
+            has_finished: rows.iter().any(|r| !r.can_cancel),
+            rows,
+        }
+    }
+}
+
+fn queue_row(item: &QueueItem) -> QueueRow {
+    let size = match item.total {
+        Some(total) => format!("{} из {}", format_size(item.bytes), format_size(total)),
+        None => format_size(item.bytes),
+    };
+    let percent = match (item.state, item.total) {
+        (ItemState::Transferred, _) => Some(100),
+        (_, Some(0)) => Some(100),
+        (_, Some(total)) => Some(item.bytes.min(total) * 100 / total),
+        (ItemState::Pending, None) => Some(0),
+        _ => None,
+    };
+
+    let (state, mut status) = match (item.state, &item.error) {
+        (ItemState::Pending, _) => ("pending", "Ожидает".to_string()),
+        // Попытка не удалась, файл ждёт повтора
+        (ItemState::Running, Some(error)) => ("running", format!("{}; повтор", error)),
+        (ItemState::Running, None) => {
+            let elapsed = item.started.map(|s| s.elapsed()).unwrap_or_default();
+            let speed = rate(item.bytes, elapsed);
+            let eta = match (item.total, speed) {
+                (Some(total), Some(speed)) if speed > 0 => {
+                    format!(
+                        ", осталось {}",
+                        duration_text(total.saturating_sub(item.bytes) / speed)
+                    )
+                }
+                _ => String::new(),
+            };
+            let speed = speed
+                .map(|s| format!(", {}/с", format_size(s)))
+                .unwrap_or_default();
+            ("running", format!("{}{}{}", size, speed, eta))
+        }
+        (ItemState::Transferred, _) => {
+            let elapsed = item.elapsed.unwrap_or_default();
+            let speed = rate(item.bytes, elapsed)
+                .map(|s| format!(", {}/с", format_size(s)))
+                .unwrap_or_default();
+            (
+                "transferred",
+                format!(
+                    "{} за {}{}{}",
+                    format_size(item.bytes),
+                    duration_text(elapsed.as_secs()),
+                    speed,
+                    if item.verified {
+                        ", проверен"
+                    } else {
+                        ""
+                    }
+                ),
+            )
+        }
+        (ItemState::Skipped(reason), _) => ("skipped", skip_reason_text(reason).to_string()),
+        (ItemState::Failed, error) => ("failed", error.clone().unwrap_or_default()),
+        (ItemState::Cancelled, _) => ("cancelled", "Отменён".to_string()),
+    };
+    if let Some(target) = &item.renamed {
+        status.push_str(&format!(", сохраняется как {}", target));
+    }
+    if item.attempt > 1 {
+        status.push_str(&format!(
+            ", попытка {} из {}",
+            item.attempt, item.max_attempts
+        ));
+    }
+
+    QueueRow {
+        id: item.id,
+        name: item.name.clone(),
+        is_upload: item.is_upload,
+        direction: if item.is_upload {
+            "Загрузка"
+        } else {
+            "Скачивание"
+        },
+        state,
+        percent,
+        status,
+        can_cancel: !item.state.is_finished(),
+        can_retry: matches!(item.state, ItemState::Failed | ItemState::Cancelled),
+    }
+}
+
+/// Скорость в байтах в секунду; `None`, пока замерять рано.
+fn rate(bytes: u64, elapsed: std::time::Duration) -> Option<u64> {
+    let secs = elapsed.as_secs_f64();
+    (secs >= 0.1).then(|| (bytes as f64 / secs) as u64)
+}
+
+/// `"42 с"`, `"3 мин 5 с"`, `"1 ч 2 мин"`.
+fn duration_text(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{} с", secs),
+        60..3600 => format!("{} мин {} с", secs / 60, secs % 60),
+        _ => format!("{} ч {} мин", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Файл из вопроса о конфликтах.
+pub struct ConflictRow {
+    pub name: String,
+    /// Размер и время изменения передаваемого файла.
+    pub source: String,
+    /// То же для файла, который будет перезаписан.
+    pub target: String,
+}
+
+/// Вопрос о конфликтах одной пачки.
+pub struct ConflictQuestionView {
+    pub id: u64,
+    pub title: &'static str,
+    pub rows: Vec<ConflictRow>,
+}
+
+/// Диалог о конфликтах, отправляется SSE-событием `conflicts`; пустой,
+/// если вопросов нет.
+#[derive(Template)]
+#[template(path = "conflict_dialog.html")]
+pub struct ConflictDialogTemplate {
+    pub questions: Vec<ConflictQuestionView>,
+}
+
+impl ConflictDialogTemplate {
+    pub fn new(questions: &[ConflictQuestion]) -> Self {
+        let questions = questions
+            .iter()
+            .map(|question| ConflictQuestionView {
+                id: question.id,
+                title: if question.is_upload {
+                    "Эти файлы уже есть на сервере"
+                } else {
+                    "Эти файлы уже есть в локальной директории"
+                },
+                rows: question
+                    .conflicts
+                    .iter()
+                    .map(|conflict| ConflictRow {
+                        name: conflict.name.clone(),
+                        source: stat_text(&conflict.source),
+                        target: stat_text(&conflict.target),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { questions }
+    }
+}
+
+/// Возможность сервера для панели «О сервере».
+pub struct FeatureRow {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub supported: bool,
+}
+
+/// Панель «О сервере»: приветствие, тип системы и возможности из FEAT.
+#[derive(Template)]
+#[template(path = "server_info.html")]
+pub struct ServerInfoTemplate {
+    pub welcome: Option<String>,
+    pub system: Option<String>,
+    /// Пусто, если сервер не ответил на FEAT.
+    pub features: Vec<FeatureRow>,
+    pub hash: String,
+    pub other: String,
+}
+
+impl ServerInfoTemplate {
+    pub fn new(capabilities: &ServerCapabilities) -> Self {
+        let c = capabilities;
+        let features = if c.feat {
+            [
+                ("MLSD", "машиночитаемые листинги", c.mlsd),
+                ("SIZE", "размер файла", c.size),
+                ("MDTM", "время изменения", c.mdtm),
+                ("MFMT", "установка времени изменения", c.mfmt),
+                ("REST STREAM", "докачка", c.rest_stream),
+                ("UTF8", "имена в UTF-8", c.utf8),
+                ("HASH", "контрольные суммы", !c.hash.is_empty()),
+                ("EPSV", "расширенный пассивный режим", c.epsv),
+            ]
+            .into_iter()
+            .map(|(name, title, supported)| FeatureRow {
+                name,
+                title,
+                supported,
+            })
+            .collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            welcome: c.welcome.clone().filter(|w| !w.is_empty()),
+            system: c.system.clone(),
+            features,
+            hash: c.hash.join(", "),
+            other: c.other.join(", "),
+        }
+    }
+}
+
+/// `"1.5 MB, 18.10.2026 14:03"`; неизвестное — `"—"`.
+fn stat_text(stat: &FileStat) -> String {
+    let size = stat.size.map_or("—".to_string(), format_size);
+    let modified = stat.modified.map_or("—".to_string(), |time| {
+        DateTime::<Local>::from(time)
+            .format("%d.%m.%Y %H:%M")
+            .to_string()
+    });
+    format!("{}, {}", size, modified)
+}
+
+#[derive(Template)]
+#[template(path = "profile_picker.html")]
+pub struct ProfilePickerTemplate {
+    pub names: Vec<String>,
+}
+
+/// Строка таблицы профилей.
+pub struct ProfileRow {
+    pub name: String,
+    pub server: String,
+    pub username: String,
+    pub mode: &'static str,
+    pub has_password: bool,
+}
+
+#[derive(Template)]
+#[template(path = "profiles.html")]
+pub struct ProfilesTemplate {
+    pub profiles: Vec<ProfileRow>,
+    pub form: ProfileFormTemplate,
+}
+
+impl ProfilesTemplate {
+    pub fn new(store: &ProfileStore) -> Self {
+        let profiles = store
+            .list()
+            .map(|p| ProfileRow {
+                name: p.name.clone(),
+                server: format!("{}:{}", p.params.host, p.params.port),
+                username: p.params.username.clone(),
+                mode: mode_label(p.options.mode),
+                has_password: store.has_password(&p.name),
+            })
+            .collect();
+        Self {
+            profiles,
+            form: ProfileFormTemplate::empty(store.can_store_passwords()),
+        }
+    }
+}
+
+/// Форма создания/редактирования профиля.
+#[derive(Template)]
+#[template(path = "profile_form.html")]
+pub struct ProfileFormTemplate {
+    /// Прежнее имя при редактировании, пусто для нового профиля.
+    pub original_name: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub mode: &'static str,
+    pub timeout_secs: u64,
+    pub remote_dir: String,
+    pub local_dir: String,
+    pub has_password: bool,
+    pub can_store_passwords: bool,
+}
+
+impl ProfileFormTemplate {
+    pub fn empty(can_store_passwords: bool) -> Self {
+        let defaults = FtpConnectParams::new("", 21, "", "");
+        Self {
+            original_name: String::new(),
+            name: String::new(),
+            host: defaults.host,
+            port: defaults.port,
+            username: defaults.username,
+            mode: mode_value(FtpMode::default()),
+            timeout_secs: defaults.timeout_secs,
+            remote_dir: String::new(),
+            local_dir: String::new(),
+            has_password: false,
+            can_store_passwords,
+        }
+    }
+
+    pub fn edit(store: &ProfileStore, profile: &Profile) -> Self {
+        Self {
+            original_name: profile.name.clone(),
+            name: profile.name.clone(),
+            host: profile.params.host.clone(),
+            port: profile.params.port,
+            username: profile.params.username.clone(),
+            mode: mode_value(profile.options.mode),
+            timeout_secs: profile.params.timeout_secs,
+            remote_dir: profile.remote_dir.clone(),
+            local_dir: profile.local_dir.clone(),
+            has_password: store.has_password(&profile.name),
+            can_store_passwords: store.can_store_passwords(),
+        }
+    }
+}
+
+/// Строка отчёта об импорте профилей.
+pub struct ImportRow {
+    pub name: String,
+    pub server: String,
+    pub username: String,
+    pub password: bool,
+    pub status: String,
+    pub notes: Vec<String>,
+}
+
+/// Предпросмотр (`applied == false`) или итог импорта из FileZilla.
+#[derive(Template)]
+#[template(path = "profile_import.html")]
+pub struct ProfileImportTemplate {
+    /// Токен разобранного файла в [`crate::profiles::filezilla::PendingImports`];
+    /// пусто в итоговом отчёте.
+    pub token: String,
+    pub rows: Vec<ImportRow>,
+    pub skipped: Vec<SkippedEntry>,
+    pub applied: bool,
+}
+
+/// Значение `<option>` для режима (совпадает с serde-представлением [`FtpMode`]).
+fn mode_value(mode: FtpMode) -> &'static str {
+    match mode {
+        FtpMode::Passive => "passive",
+        FtpMode::ExtendedPassive => "extended_passive",
+        FtpMode::Active => "active",
+    }
+}
+
+fn mode_label(mode: FtpMode) -> &'static str {
+    match mode {
+        FtpMode::Passive => "PASV",
+        FtpMode::ExtendedPassive => "EPSV",
+        FtpMode::Active => "PORT",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn footer_escapes_server_text() {
+        let status = Status {
+            connected: false,
+            connection_error: Some("<img src=x onerror=alert(1)>".to_string()),
+            transfer: None,
+            bandwidth: Some(1024),
+            server: None,
+        };
+        let html = StatusFooterTemplate::new(&status).render().unwrap();
+        assert!(!html.contains("<img"), "{html}");
+        assert!(
+            html.contains("&lt;img src=x onerror=alert(1)&gt;"),
+            "{html}"
+        );
+        assert!(html.contains("скорость до 1.0 KB/с"), "{html}");
+
+        let status = Status {
+            connected: true,
+            transfer: Some("<b>file</b>.txt".to_string()),
+            ..status
+        };
+        let html = StatusFooterTemplate::new(&status).render().unwrap();
+        assert!(
+            html.starts_with("<p>🔄 &lt;b&gt;file&lt;/b&gt;.txt"),
+            "{html}"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// Настройки защиты от перебора (секция `[throttle]` конфигурации).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrottleSettings {
+    /// Сколько неудачных попыток подряд допускается без блокировки.
+    pub max_failures: u32,
+    /// Первая блокировка в секундах, каждая следующая вдвое дольше.
+    pub base_lockout_secs: u64,
+    /// Верхняя граница блокировки в секундах.
+    pub max_lockout_secs: u64,
+    /// Счётчик неудач сбрасывается после такого простоя (в секундах).
+    pub reset_after_secs: u64,
+}
+
+impl Default for ThrottleSettings {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            base_lockout_secs: 5,
+            max_lockout_secs: 15 * 60,
+            reset_after_secs: 60 * 60,
+        }
+    }
+}
+
+struct Attempts {
+    failures: u32,
+    last_attempt: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Ограничитель попыток подключения с экспоненциальной блокировкой.
+///
+/// Счётчики ведутся по произвольным ключам — обработчики используют
+/// IP клиента и целевой хост, чтобы перебор нельзя было обойти,
+/// меняя только одно из них. Попытка засчитывается как неудачная ещё
+/// в [`LoginThrottle::check`], а успешный вход её снимает: так параллельные
+/// попытки не проходят проверку, пока предыдущие ещё не завершились.
+pub struct LoginThrottle {
+    settings: ThrottleSettings,
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl LoginThrottle {
+    pub fn new(settings: ThrottleSettings) -> Self {
+        Self {
+            settings,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Начать попытку подключения: проверить, не заблокирован ли хотя бы
+    /// один из ключей, и сразу учесть попытку как неудачную.
+    ///
+    /// Возвращает оставшееся время блокировки, если она действует; тогда
+    /// попытка не учитывается.
+    pub async fn check(&self, keys: &[String]) -> Result<(), Duration> {
+        let mut attempts = self.attempts.lock().await;
+        self.reserve(&mut attempts, keys, Instant::now())
+    }
+
+    /// Снять счётчики ключей после успешного входа.
+    pub async fn record_success(&self, keys: &[String]) {
+        let mut attempts = self.attempts.lock().await;
+        for key in keys {
+            attempts.remove(key);
+        }
+    }
+
+    fn reserve(
+        &self,
+        attempts: &mut HashMap<String, Attempts>,
+        keys: &[String],
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let reset_after = Duration::from_secs(self.settings.reset_after_secs);
+        attempts.retain(|_, a| {
+            now - a.last_attempt < reset_after || a.locked_until.is_some_and(|until| until > now)
+        });
+
+        let remaining = keys
+            .iter()
+            .filter_map(|k| attempts.get(k)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max();
+        if let Some(left) = remaining {
+            return Err(left);
+        }
+
+        for key in keys {
+            let entry = attempts.entry(key.clone()).or_insert(Attempts {
+                failures: 0,
+                last_attempt: now,
+                locked_until: None,
+            });
+            entry.failures += 1;
+            entry.last_attempt = now;
+
+            if let Some(over) = entry.failures.checked_sub(self.settings.max_failures + 1) {
+                let lockout = self
+                    .settings
+                    .base_lockout_secs
+                    .saturating_mul(1u64.checked_shl(over).unwrap_or(u64::MAX))
+                    .min(self.settings.max_lockout_secs);
+                entry.locked_until = Some(now + Duration::from_secs(lockout));
+                log::warn!(
+                    "Блокировка {} на {} с после {} неудачных попыток",
+                    key,
+                    lockout,
+                    entry.failures
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(ThrottleSettings {
+            max_failures: 2,
+            base_lockout_secs: 10,
+            max_lockout_secs: 40,
+            reset_after_secs: 60,
+        })
+    }
+
+    fn keys() -> Vec<String> {
+        vec![
+            "ip:192.0.2.1".to_string(),
+            "host:ftp.example.com".to_string(),
+        ]
+    }
+
+    #[test]
+    fn locks_out_after_repeated_failures() {
+        let throttle = throttle();
+        let mut attempts = HashMap::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        for _ in 0..3 {
+            assert!(throttle.reserve(&mut attempts, &keys(), start).is_ok());
+        }
+        // Попытка не завершилась, но уже учтена: следующая заблокирована
+        assert_eq!(
+            throttle.reserve(&mut attempts, &keys(), at(1)),
+            Err(Duration::from_secs(9))
+        );
+        // Блокировка по одному ключу действует и для другого набора ключей
+        let other = ["ip:192.0.2.1".to_string(), "host:other".to_string()];
+        assert!(throttle.reserve(&mut attempts, &other, at(1)).is_err());
+
+        // Каждая следующая неудача удваивает блокировку до предела
+        assert!(throttle.reserve(&mut attempts, &keys(), at(10)).is_ok());
+        assert_eq!(
+            throttle.reserve(&mut attempts, &keys(), at(10)),
+            Err(Duration::from_secs(20))
+        );
+        assert!(throttle.reserve(&mut attempts, &keys(), at(30)).is_ok());
+        assert!(throttle.reserve(&mut attempts, &keys(), at(70)).is_ok());
+        assert_eq!(
+            throttle.reserve(&mut attempts, &keys(), at(70)),
+            Err(Duration::from_secs(40))
+        );
+    }
+
+    #[test]
+    fn counters_expire_after_quiet_window() {
+        let throttle = throttle();
+        let mut attempts = HashMap::new();
+        let start = Instant::now();
+
+        for _ in 0..2 {
+            assert!(throttle.reserve(&mut attempts, &keys(), start).is_ok());
+        }
+        let later = start + Duration::from_secs(60);
+        assert!(throttle.reserve(&mut attempts, &keys(), later).is_ok());
+        assert_eq!(attempts["ip:192.0.2.1"].failures, 1);
+    }
+
+    #[tokio::test]
+    async fn success_releases_the_attempt() {
+        let throttle = throttle();
+        for _ in 0..10 {
+            assert!(throttle.check(&keys()).await.is_ok());
+            throttle.record_success(&keys()).await;
+        }
+        assert!(throttle.attempts.lock().await.is_empty());
+
+        for _ in 0..3 {
+            throttle.check(&keys()).await.unwrap();
+        }
+        assert!(throttle.check(&keys()).await.is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    Router,
+    extract::{Host, State},
+    http::Uri,
+    response::Redirect,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use serde::Deserialize;
+
+/// Настройки HTTPS (секция `[tls]` конфигурации).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// PEM-файл с цепочкой сертификатов.
+    pub cert: PathBuf,
+    /// PEM-файл с закрытым ключом.
+    pub key: PathBuf,
+    /// Как часто проверять файлы на изменение, в секундах.
+    #[serde(default = "default_reload_interval")]
+    pub reload_interval_secs: u64,
+    /// Порт HTTP-слушателя, перенаправляющего на HTTPS. Без него HTTP не слушается.
+    #[serde(default)]
+    pub redirect_http_port: Option<u16>,
+}
+
+fn default_reload_interval() -> u64 {
+    30
+}
+
+/// Загрузить сертификат и ключ.
+pub async fn load(settings: &TlsSettings) -> std::io::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&settings.cert, &settings.key).await
+}
+
+/// Фоновая задача: перечитывает сертификат и ключ, когда меняется время
+/// их модификации. При ошибке продолжает работать со старыми.
+pub fn spawn_reloader(config: RustlsConfig, settings: TlsSettings) {
+    tokio::spawn(async move {
+        let mut last = modified(&settings);
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(settings.reload_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            let current = modified(&settings);
+            if current == last {
+                continue;
+            }
+            // Запоминаем сразу: при ошибке (файлы записаны наполовину)
+            // попробуем снова, когда они изменятся ещё раз
+            last = current;
+            match config
+                .reload_from_pem_file(&settings.cert, &settings.key)
+                .await
+            {
+                Ok(()) => log::info!("TLS-сертификат перечитан из {}", settings.cert.display()),
+                Err(e) => log::error!("Не удалось перечитать TLS-сертификат: {}", e),
+            }
+        }
+    });
+}
+
+fn modified(settings: &TlsSettings) -> (Option<SystemTime>, Option<SystemTime>) {
+    let mtime = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    (mtime(&settings.cert), mtime(&settings.key))
+}
+
+/// Приложение HTTP-слушателя: любой запрос перенаправляется на HTTPS.
+pub fn redirect_app(https_port: u16) -> Router {
+    Router::new()
+        .fallback(redirect_to_https)
+        .with_state(https_port)
+}
+
+async fn redirect_to_https(State(https_port): State<u16>, Host(host): Host, uri: Uri) -> Redirect {
+    // Отрезаем порт, не трогая IPv6-адрес в скобках: "[::1]:80" -> "[::1]"
+    let hostname = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host.as_str(),
+    };
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let target = if https_port == 443 {
+        format!("https://{}{}", hostname, path)
+    } else {
+        format!("https://{}:{}{}", hostname, https_port, path)
+    };
+    Redirect::permanent(&target)
+}
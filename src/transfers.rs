@@ -0,0 +1,258 @@
+//! Реестр фоновых передач, запущенных через JSON API.
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ftp_fs::{SkipReason, TransferItem, TransferOptions, TransferOutcome, TransferReport};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::{
+    conflicts::ConflictMode,
+    error::{AppError, ErrorBody},
+    routes,
+    state::AppState,
+};
+
+/// Сколько последних передач помнит реестр.
+const HISTORY_LIMIT: usize = 100;
+
+/// Направление передачи.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// Состояние передачи.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Итог передачи одного файла.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemOutcome {
+    Transferred,
+    Skipped,
+    Failed,
+}
+
+/// Почему файл пропущен.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemSkipReason {
+    /// Директории не передаются.
+    Directory,
+    /// Устройство, сокет или FIFO.
+    NotRegularFile,
+    /// Соединение потеряно на одном из предыдущих файлов.
+    Aborted,
+    /// Отменён в панели очереди передач.
+    Cancelled,
+    /// Файл уже есть в месте назначения, политика — пропускать.
+    Exists,
+    /// В месте назначения файл не старее или того же размера.
+    UpToDate,
+}
+
+impl From<SkipReason> for ItemSkipReason {
+    fn from(reason: SkipReason) -> Self {
+        match reason {
+            SkipReason::Directory => Self::Directory,
+            SkipReason::NotRegularFile => Self::NotRegularFile,
+            SkipReason::Aborted => Self::Aborted,
+            SkipReason::Cancelled => Self::Cancelled,
+            SkipReason::Exists => Self::Exists,
+            SkipReason::UpToDate => Self::UpToDate,
+        }
+    }
+}
+
+/// Результат по одному запрошенному файлу.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ItemResult {
+    pub name: String,
+    pub outcome: ItemOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<ItemSkipReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    /// Размер на сервере совпал с переданным (команда SIZE).
+    pub verified: bool,
+    /// Сколько попыток понадобилось; 0 — передача не начиналась.
+    pub attempts: u32,
+    /// Под каким именем файл сохранён, если под запрошенным уже был файл.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renamed: Option<String>,
+}
+
+impl From<TransferItem> for ItemResult {
+    fn from(item: TransferItem) -> Self {
+        let (outcome, reason, error) = match item.outcome {
+            TransferOutcome::Transferred => (ItemOutcome::Transferred, None, None),
+            TransferOutcome::Skipped(reason) => (ItemOutcome::Skipped, Some(reason.into()), None),
+            TransferOutcome::Failed(e) => (
+                ItemOutcome::Failed,
+                None,
+                Some(ErrorBody::from(&AppError::Fs(e))),
+            ),
+        };
+        Self {
+            name: item.name,
+            outcome,
+            reason,
+            error,
+            bytes: item.bytes,
+            duration_ms: item.duration.as_millis() as u64,
+            verified: item.verified,
+            attempts: item.attempts,
+            renamed: item.renamed,
+        }
+    }
+}
+
+/// Одна передача: пачка файлов в одном направлении.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TransferJob {
+    pub id: u64,
+    pub direction: Direction,
+    pub files: Vec<String>,
+    pub state: JobState,
+    /// Время запуска, секунды Unix.
+    pub started_at: u64,
+    /// Время завершения, секунды Unix.
+    pub finished_at: Option<u64>,
+    /// Ошибка, из-за которой пачка не началась.
+    pub error: Option<ErrorBody>,
+    /// Результаты по файлам, заполняются по завершении. Передача
+    /// считается неудачной, если хотя бы один файл завершился ошибкой.
+    pub items: Vec<ItemResult>,
+}
+
+/// Реестр передач: запущенные и последние [`HISTORY_LIMIT`] завершённых.
+#[derive(Default)]
+pub struct TransferJobs {
+    next_id: AtomicU64,
+    jobs: Mutex<VecDeque<TransferJob>>,
+}
+
+impl TransferJobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Зарегистрировать передачу и запустить её в фоне.
+    ///
+    /// Передачи выполняются по очереди: все они ждут общего FTP-соединения.
+    pub async fn spawn(
+        &self,
+        state: AppState,
+        direction: Direction,
+        files: Vec<String>,
+        options: TransferOptions,
+        conflict: Option<ConflictMode>,
+    ) -> TransferJob {
+        let job = TransferJob {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            direction,
+            files: files.clone(),
+            state: JobState::Running,
+            started_at: unix_now(),
+            finished_at: None,
+            error: None,
+            items: Vec::new(),
+        };
+
+        {
+            let mut jobs = self.jobs.lock().await;
+            if jobs.len() >= HISTORY_LIMIT
+                && let Some(pos) = jobs.iter().position(|j| j.state != JobState::Running)
+            {
+                jobs.remove(pos);
+            }
+            jobs.push_back(job.clone());
+        }
+
+        let id = job.id;
+        tokio::spawn(async move {
+            let is_upload = direction == Direction::Upload;
+            let result = routes::handle_transfer(
+                state.clone(),
+                state.local_fs.clone(),
+                files,
+                is_upload,
+                options,
+                conflict,
+                None,
+            )
+            .await;
+            match &result {
+                Ok(report) if !report.is_success() => log::warn!(
+                    "Передача #{}: ошибок {} из {}",
+                    id,
+                    report.failed(),
+                    report.items.len()
+                ),
+                Ok(_) => {}
+                Err(e) => log::warn!("Передача #{} завершилась ошибкой: {}", id, e),
+            }
+            state.transfers.finish(id, result).await;
+        });
+
+        job
+    }
+
+    async fn finish(&self, id: u64, result: Result<TransferReport, AppError>) {
+        let mut jobs = self.jobs.lock().await;
+        if let Some(job) = jobs.iter_mut().find(|j| j.id == id) {
+            job.finished_at = Some(unix_now());
+            match result {
+                Ok(report) => {
+                    job.state = if report.is_success() {
+                        JobState::Completed
+                    } else {
+                        JobState::Failed
+                    };
+                    job.items = report.items.into_iter().map(ItemResult::from).collect();
+                }
+                Err(e) => {
+                    job.state = JobState::Failed;
+                    job.error = Some(ErrorBody::from(&e));
+                }
+            }
+        }
+    }
+
+    pub async fn list(&self) -> Vec<TransferJob> {
+        self.jobs.lock().await.iter().cloned().collect()
+    }
+
+    pub async fn get(&self, id: u64) -> Option<TransferJob> {
+        self.jobs.lock().await.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Количество выполняющихся передач.
+    pub async fn running(&self) -> usize {
+        let jobs = self.jobs.lock().await;
+        jobs.iter().filter(|j| j.state == JobState::Running).count()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}